@@ -0,0 +1,154 @@
+//! Throughput of the processor on generated datasets, and the memory it keeps per transaction.
+//! Run with `cargo bench`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{BatchSize, Criterion, Throughput};
+use txn_processor::csv_utils::CsvSource;
+use txn_processor::generator::{self, Generator, GeneratorConfig, Row};
+use txn_processor::input::{InputFormat, MemorySource};
+use txn_processor::parallel;
+use txn_processor::processor::Processor;
+use txn_processor::types::Txn;
+
+// Counts the bytes currently allocated, to measure the state of the processor
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const COUNT: u32 = 100_000;
+
+fn datasets() -> Vec<(&'static str, GeneratorConfig)> {
+    let default = GeneratorConfig {
+        count: COUNT,
+        seed: 1,
+        ..Default::default()
+    };
+    vec![
+        ("default", default.clone()),
+        (
+            "disputes",
+            GeneratorConfig {
+                disputes: 0.2,
+                resolves: 0.5,
+                chargebacks: 0.001,
+                ..default.clone()
+            },
+        ),
+        (
+            "few_clients",
+            GeneratorConfig {
+                clients: 10,
+                // Otherwise they are all locked right away
+                chargebacks: 0.0,
+                ..default
+            },
+        ),
+    ]
+}
+
+fn txns(config: &GeneratorConfig) -> Vec<Txn> {
+    Generator::new(config.clone())
+        .filter_map(|row| match row {
+            Row::Txn(txn) => Some(txn),
+            Row::Malformed { .. } => None,
+        })
+        .collect()
+}
+
+fn process(txns: &[Txn]) -> Processor {
+    let mut p = Processor::default();
+    for txn in txns {
+        let _ = p.process_txn(txn);
+    }
+    p
+}
+
+// Prints how much memory the processor keeps after processing each dataset
+fn memory() {
+    for (name, config) in datasets() {
+        let txns = txns(&GeneratorConfig {
+            count: 1_000_000,
+            ..config
+        });
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        let p = process(&txns);
+        let bytes = ALLOCATED.load(Ordering::Relaxed) - before;
+        println!(
+            "memory/{}: {:.1} MiB after {} transactions, {:.1} bytes each",
+            name,
+            bytes as f64 / (1 << 20) as f64,
+            txns.len(),
+            bytes as f64 / txns.len() as f64
+        );
+        drop(p);
+    }
+}
+
+fn benches(c: &mut Criterion) {
+    for (name, config) in datasets() {
+        let txns = txns(&config);
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(txns.len() as u64));
+        group.sample_size(20);
+
+        group.bench_function("process_txn", |b| b.iter(|| process(&txns)));
+
+        let mut csv = Vec::new();
+        generator::write(&mut csv, InputFormat::Csv, Generator::new(config.clone()))
+            .expect("Cannot write CSV");
+        group.bench_function("csv", |b| {
+            b.iter(|| {
+                let mut src = CsvSource::from_reader("bench".to_string(), csv.as_slice())
+                    .expect("Cannot read CSV");
+                let mut p = Processor::default();
+                p.process_source(&mut src, |_, _| {});
+                p
+            })
+        });
+
+        for threads in [1, 2, 4, 8] {
+            group.bench_function(format!("parallel_{}", threads), |b| {
+                b.iter_batched(
+                    || MemorySource::new(txns.clone()),
+                    |mut src| {
+                        parallel::process_source(&mut src, threads, Processor::default, |_, _| {})
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+fn main() {
+    memory();
+    let mut c = Criterion::default().configure_from_args();
+    benches(&mut c);
+    c.final_summary();
+}
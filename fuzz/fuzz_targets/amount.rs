@@ -0,0 +1,32 @@
+//! Parses arbitrary strings as amounts, and checks that the amounts that parse are read back as
+//! the same value once written.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use txn_processor::amount::{Amount, Rounding};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(amount) = Amount::try_from(s.to_string()) else {
+        return;
+    };
+
+    let written = amount.to_string();
+    assert_eq!(
+        Amount::try_from(written.clone()).ok(),
+        Some(amount),
+        "`{}` is written as `{}`",
+        s,
+        written
+    );
+
+    // None of these can panic, whatever the amount
+    let _ = amount + Amount::from(i64::MAX);
+    let _ = amount - Amount::from(i64::MAX);
+    let _ = amount.percent(amount, 2, Rounding::HalfUp);
+    let _ = amount.round(0, Rounding::Up);
+    let _ = amount.decimals();
+});
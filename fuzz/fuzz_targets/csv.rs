@@ -0,0 +1,17 @@
+//! Reads arbitrary bytes as a CSV file of transactions, and checks that every transaction that
+//! is read is read back as the same transaction once written as a row.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use txn_processor::csv_utils::{parse_txn, CsvSource};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(src) = CsvSource::from_reader("fuzz".to_string(), data) else {
+        return;
+    };
+    for txn in src.flatten() {
+        let row = txn.to_string();
+        assert_eq!(parse_txn(&row), Ok(txn), "`{}`", row);
+    }
+});
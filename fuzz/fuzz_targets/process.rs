@@ -0,0 +1,50 @@
+//! Processes arbitrary CSV rows, one per line, with fees, interest and the invariant checks
+//! enabled, so that every code path of `Processor::process_txn` can be reached, and checks that
+//! no invariant is ever broken. Disputes are strict, since otherwise disputing a transaction
+//! twice breaks them on purpose.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use txn_processor::amount::Rounding;
+use txn_processor::config::{DisputePolicy, ProcessorConfig};
+use txn_processor::csv_utils::parse_txn;
+use txn_processor::fees::{FeeRule, FeeSchedule};
+use txn_processor::output::Output;
+use txn_processor::processor::Processor;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let mut p = Processor::new(ProcessorConfig {
+        disputes: DisputePolicy {
+            strict: true,
+            ..DisputePolicy::default()
+        },
+        ..ProcessorConfig::default()
+    });
+    p.set_fees(FeeSchedule {
+        house_account: 0,
+        withdrawal: Some(FeeRule {
+            percent: 10000.into(),
+            ..FeeRule::default()
+        }),
+        chargeback: Some(FeeRule {
+            flat: 50000.into(),
+            ..FeeRule::default()
+        }),
+        ..FeeSchedule::default()
+    });
+    p.set_interest_rate(25000.into(), Rounding::HalfEven);
+    p.set_check_invariants(true);
+
+    for txn in s.lines().filter_map(|line| parse_txn(line).ok()) {
+        let _ = p.process_txn(&txn);
+    }
+    assert!(p.get_violations().is_empty(), "{:?}", p.get_violations());
+    for acct in p.get_accounts() {
+        let _ = Output::from(acct);
+    }
+});
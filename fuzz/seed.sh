@@ -0,0 +1,15 @@
+#!/bin/sh
+# Derives the seed corpus of every fuzz target from the files in testdata/
+set -e
+cd "$(dirname "$0")"
+mkdir -p corpus/amount corpus/csv corpus/process
+
+for f in ../testdata/*.csv; do
+    name=$(basename "$f" .csv)
+    cp "$f" "corpus/csv/$name"
+    # The process target reads rows without a header
+    tail -n +2 "$f" > "corpus/process/$name"
+    tail -n +2 "$f" | tr ',' '\n' | grep -E '^-?[0-9.]+$' | while read -r amount; do
+        printf '%s' "$amount" > "corpus/amount/$amount"
+    done
+done
@@ -1,18 +1,98 @@
 use std::{
-    cmp::min,
     fmt::Display,
     ops::{Add, Sub},
 };
 
+use clap::ValueEnum;
+use serde::Deserialize;
+
 use crate::types::Error;
 
 const DECIMALS: usize = 4;
+const SCALE: u64 = 10000;
 
 // I tried using the primitive_fixed_point_decimal and the fixed crates, but they both had problems with
 // serde+csv. This is a very-poor-man's version of a fixed decimal.
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Ord, Hash, Eq)]
+// Deserialized from strings only, e.g. `"1.5"`, since floats would not be precise.
+#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd, Ord, Hash, Eq, Deserialize)]
+#[serde(try_from = "String")]
 pub struct Amount(i64);
 
+/// How to round a result that has more decimals than an `Amount` can hold.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// To the nearest value, and ties to the even one (banker's rounding)
+    #[default]
+    HalfEven,
+    /// To the nearest value, and ties away from zero
+    HalfUp,
+    /// Towards zero, i.e. truncating
+    Down,
+    /// Away from zero
+    Up,
+}
+
+impl Rounding {
+    // Divides `n` by `d`, which must be positive
+    fn div(self, n: i128, d: i128) -> i128 {
+        let (q, r) = (n.div_euclid(d), n.rem_euclid(d));
+        if r == 0 {
+            return q;
+        }
+        // `q` is rounded down, i.e. towards zero only if `n` is positive
+        let away = if n > 0 { q + 1 } else { q };
+        let towards = if n > 0 { q } else { q + 1 };
+        match self {
+            Rounding::Down => towards,
+            Rounding::Up => away,
+            _ if 2 * r > d => q + 1,
+            _ if 2 * r < d => q,
+            Rounding::HalfUp => away,
+            Rounding::HalfEven if q % 2 == 0 => q,
+            Rounding::HalfEven => q + 1,
+        }
+    }
+}
+
+impl Amount {
+    /// `pct` percent of this amount, rounded to `decimals` decimals (at most 4).
+    pub fn percent(self, pct: Amount, decimals: usize, rounding: Rounding) -> Amount {
+        let unit = unit(decimals);
+        let n = self.0 as i128 * pct.0 as i128;
+        let q = rounding.div(n, 100 * SCALE as i128 * unit) * unit;
+        Amount(q.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    /// This amount rounded to `decimals` decimals (at most 4).
+    pub fn round(self, decimals: usize, rounding: Rounding) -> Amount {
+        let unit = unit(decimals);
+        let q = rounding.div(self.0 as i128, unit) * unit;
+        Amount(q.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    /// The sum of both amounts, or `None` if it's out of range.
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
+    /// The number of decimals needed to write this amount exactly, at most 4.
+    pub fn decimals(self) -> usize {
+        let mut n = self.0;
+        let mut decimals = DECIMALS;
+        while decimals > 0 && n % 10 == 0 {
+            n /= 10;
+            decimals -= 1;
+        }
+        decimals
+    }
+}
+
+// The smallest amount with `decimals` decimals, in ten-thousandths
+fn unit(decimals: usize) -> i128 {
+    10i128.pow(DECIMALS.saturating_sub(decimals) as u32)
+}
+
 impl Display for Amount {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s: String = self.into();
@@ -36,14 +116,12 @@ impl TryFrom<String> for Amount {
     type Error = Error;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        let idx = value.find(".").unwrap_or(value.len());
-        let (int_part, mut frac_part) = value.split_at(idx);
-
-        frac_part = if frac_part.is_empty() {
-            "0000"
-        } else {
-            &frac_part[1..min(DECIMALS + 1, frac_part.len())]
-        };
+        let (int_part, frac_part) = value.split_once('.').unwrap_or((&value, ""));
+        // Extra decimals are dropped. Counted in chars, since the input can be anything.
+        let frac_part = frac_part
+            .char_indices()
+            .nth(DECIMALS)
+            .map_or(frac_part, |(i, _)| &frac_part[..i]);
 
         let s = format!("{}{:0<width$}", int_part, frac_part, width = DECIMALS);
         s.parse()
@@ -59,18 +137,26 @@ impl TryFrom<String> for Amount {
 
 impl From<&Amount> for String {
     fn from(value: &Amount) -> Self {
-        let frac_part = format!("{:0<width$}", value.0 % 10000, width = DECIMALS).as_str()
-            [0..DECIMALS]
-            .to_owned();
-        format!("{}.{}", value.0 / 10000, frac_part)
+        // Work on the absolute value, otherwise the fractional part of e.g. -0.5 would lose its sign
+        let sign = if value.0 < 0 { "-" } else { "" };
+        let abs = value.0.unsigned_abs();
+        format!(
+            "{}{}.{:0>width$}",
+            sign,
+            abs / SCALE,
+            abs % SCALE,
+            width = DECIMALS
+        )
     }
 }
 
+// Saturating, so that absurd inputs can't make the processor panic or wrap around. Amounts
+// anywhere near the limits (about 922 trillion) are not expected in practice.
 impl Add for Amount {
     type Output = Amount;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Amount(self.0 + rhs.0)
+        Amount(self.0.saturating_add(rhs.0))
     }
 }
 
@@ -78,7 +164,7 @@ impl Sub for Amount {
     type Output = Amount;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Amount(self.0 - rhs.0)
+        Amount(self.0.saturating_sub(rhs.0))
     }
 }
 
@@ -92,6 +178,21 @@ mod tests {
         let actual: String = (&sut).into();
 
         assert_eq!(actual, "1234.5678");
+
+        let sut = Amount(500);
+        let actual: String = (&sut).into();
+
+        assert_eq!(actual, "0.0500");
+
+        let sut = Amount(-5000);
+        let actual: String = (&sut).into();
+
+        assert_eq!(actual, "-0.5000");
+
+        let sut = Amount(-12345678);
+        let actual: String = (&sut).into();
+
+        assert_eq!(actual, "-1234.5678");
     }
 
     #[test]
@@ -120,6 +221,53 @@ mod tests {
         let actual: Amount = sut.try_into().expect("Error unmarshalling -1234.5678");
 
         assert_eq!(actual, Amount(-12345678));
+
+        for bad in ["1.234\u{e9}", "\u{e9}", "1.2.3", "99999999999999999"] {
+            assert!(Amount::try_from(bad.to_string()).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_percent() {
+        let amt = Amount(12345); // 1.2345
+        assert_eq!(amt.percent(Amount(100000), 4, Rounding::Down), Amount(1234));
+        assert_eq!(amt.percent(Amount(100000), 4, Rounding::Up), Amount(1235));
+        // 2.5% of 1.2345 is 0.0308625
+        assert_eq!(
+            amt.percent(Amount(25000), 4, Rounding::HalfEven),
+            Amount(309)
+        );
+        assert_eq!(amt.percent(Amount(-25000), 4, Rounding::Down), Amount(-308));
+        assert_eq!(
+            amt.percent(Amount(25000), 2, Rounding::HalfEven),
+            Amount(300)
+        );
+        assert_eq!(amt.percent(Amount(25000), 0, Rounding::Up), Amount(10000));
+
+        // Ties: 50% of 0.0001 and 0.0003
+        for (n, even, up) in [(1, 0, 1), (3, 2, 2), (-1, 0, -1)] {
+            let pct = Amount(500000);
+            assert_eq!(Amount(n).percent(pct, 4, Rounding::HalfEven), Amount(even));
+            assert_eq!(Amount(n).percent(pct, 4, Rounding::HalfUp), Amount(up));
+        }
+    }
+
+    #[test]
+    fn test_round() {
+        let amt = Amount(12350); // 1.2350
+        assert_eq!(amt.round(4, Rounding::Up), amt);
+        assert_eq!(amt.round(2, Rounding::HalfEven), Amount(12400));
+        assert_eq!(amt.round(2, Rounding::Down), Amount(12300));
+        assert_eq!(Amount(-12250).round(2, Rounding::HalfEven), Amount(-12200));
+        assert_eq!(Amount(-12250).round(2, Rounding::HalfUp), Amount(-12300));
+        assert_eq!(amt.round(0, Rounding::Up), Amount(20000));
+    }
+
+    #[test]
+    fn test_decimals() {
+        for (n, decimals) in [(0, 0), (10000, 0), (-15000, 1), (12300, 2), (1, 4)] {
+            assert_eq!(Amount(n).decimals(), decimals);
+        }
     }
 
     #[test]
@@ -131,6 +279,9 @@ mod tests {
         assert_eq!(actual, Amount(111100));
 
         let actual = Amount(1234) - Amount(4321);
-        assert_eq!(actual, Amount(-3087))
+        assert_eq!(actual, Amount(-3087));
+
+        assert_eq!(Amount(i64::MAX) + Amount(1), Amount(i64::MAX));
+        assert_eq!(Amount(i64::MIN) - Amount(1), Amount(i64::MIN));
     }
 }
@@ -0,0 +1,99 @@
+use std::{
+    io::{stdout, BufWriter},
+    path::PathBuf,
+};
+
+use clap::Parser;
+use log::error;
+use txn_processor::generator::{self, Generator, GeneratorConfig};
+use txn_processor::input::InputFormat;
+use txn_processor::output;
+
+#[derive(Parser, Debug)]
+#[command(about = "Generates a synthetic file of transactions, the same for the same options")]
+struct Args {
+    /// Write the transactions to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Format of the transactions. `auto` picks it from the extension of the output file, and
+    /// defaults to CSV.
+    #[arg(short, long, value_enum, default_value_t = InputFormat::Auto)]
+    format: InputFormat,
+
+    /// Number of clients, with ids from 1
+    #[arg(short, long, default_value_t = GeneratorConfig::default().clients)]
+    clients: u16,
+
+    /// Number of rows
+    #[arg(short = 'n', long, default_value_t = GeneratorConfig::default().count)]
+    count: u32,
+
+    /// Share of the rows that are withdrawals
+    #[arg(long, default_value_t = GeneratorConfig::default().withdrawals)]
+    withdrawals: f64,
+
+    /// Share of the rows that are transfers
+    #[arg(long, default_value_t = GeneratorConfig::default().transfers)]
+    transfers: f64,
+
+    /// Share of the rows that are disputes
+    #[arg(long, default_value_t = GeneratorConfig::default().disputes)]
+    disputes: f64,
+
+    /// Share of the disputes that are resolved
+    #[arg(long, default_value_t = GeneratorConfig::default().resolves)]
+    resolves: f64,
+
+    /// Share of the disputes that are charged back
+    #[arg(long, default_value_t = GeneratorConfig::default().chargebacks)]
+    chargebacks: f64,
+
+    /// Share of the rows that can't be parsed
+    #[arg(long, default_value_t = GeneratorConfig::default().malformed)]
+    malformed: f64,
+
+    /// Seed of the random number generator
+    #[arg(short, long, default_value_t = GeneratorConfig::default().seed)]
+    seed: u64,
+}
+
+fn main() {
+    env_logger::init();
+
+    let args = Args::parse();
+    let config = GeneratorConfig {
+        clients: args.clients,
+        count: args.count,
+        withdrawals: args.withdrawals,
+        transfers: args.transfers,
+        disputes: args.disputes,
+        resolves: args.resolves,
+        chargebacks: args.chargebacks,
+        malformed: args.malformed,
+        seed: args.seed,
+    };
+    if let Err(e) = config.validate() {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+
+    // The output file can't be sniffed like an input file, so only its extension counts
+    let format = match (args.format, &args.output) {
+        (InputFormat::Auto, Some(path)) => {
+            InputFormat::from_extension(path).unwrap_or(InputFormat::Csv)
+        }
+        (InputFormat::Auto, None) => InputFormat::Csv,
+        (format, _) => format,
+    };
+
+    let rows = Generator::new(config);
+    let result = match &args.output {
+        Some(path) => output::write_atomic(path, |w| generator::write(w, format, rows)),
+        None => generator::write(BufWriter::new(stdout().lock()), format, rows),
+    };
+    if let Err(e) = result {
+        error!("Error while writing transactions: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,90 @@
+//! Per-client configuration, loaded from a CSV file such as:
+//!
+//! ```csv
+//! client,overdraft_limit
+//! 1,100
+//! 2,
+//! ```
+//!
+//! Empty values mean that the client has the default setting.
+
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::amount::Amount;
+use crate::types::{ClientId, Error};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClientConfig {
+    /// How far below zero withdrawals and transfers can take the available funds
+    pub overdraft_limit: Option<Amount>,
+}
+
+// `#[serde(flatten)]` doesn't work with the csv crate, so all columns are repeated here
+#[derive(Deserialize)]
+struct Row {
+    client: ClientId,
+    overdraft_limit: Option<Amount>,
+}
+
+pub fn load(path: &Path) -> Result<HashMap<ClientId, ClientConfig>, Error> {
+    let name = path.display().to_string();
+    let err = |e: String| Error::Deserialization(name.clone(), e);
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| err(e.to_string()))?;
+
+    let mut clients = HashMap::new();
+    for row in rdr.deserialize::<Row>() {
+        let row = row.map_err(|e| err(e.to_string()))?;
+        if row.overdraft_limit.is_some_and(|l| l < 0.into()) {
+            return Err(err(format!(
+                "Negative overdraft limit for client {}",
+                row.client
+            )));
+        }
+        let config = ClientConfig {
+            overdraft_limit: row.overdraft_limit,
+        };
+        clients.insert(row.client, config);
+    }
+    Ok(clients)
+}
+
+/// The overdraft limits of all clients that have one, as `Processor::set_overdraft_limits` takes
+/// them.
+pub fn overdraft_limits(clients: &HashMap<ClientId, ClientConfig>) -> HashMap<ClientId, Amount> {
+    clients
+        .iter()
+        .filter_map(|(client, c)| c.overdraft_limit.map(|l| (*client, l)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("clients.csv");
+        fs::write(&path, "client, overdraft_limit\n1, 100.5\n2,\n").expect("Cannot write file");
+
+        let clients = load(&path).expect("Cannot load clients");
+        assert_eq!(clients.len(), 2);
+        assert_eq!(clients[&2], ClientConfig::default());
+        assert_eq!(
+            overdraft_limits(&clients),
+            HashMap::from([(1, 1005000.into())])
+        );
+
+        fs::write(&path, "client,overdraft_limit\n1,-1\n").expect("Cannot write file");
+        assert!(matches!(load(&path), Err(Error::Deserialization(..))));
+        fs::write(&path, "client,overdraft_limit\nx,1\n").expect("Cannot write file");
+        assert!(matches!(load(&path), Err(Error::Deserialization(..))));
+    }
+}
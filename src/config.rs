@@ -0,0 +1,247 @@
+//! The policies of a `Processor`, configured in a TOML file like:
+//!
+//! ```toml
+//! precision = 2
+//! ordered_timestamps = true
+//! wall_clock = true
+//! check_invariants = true
+//! locked_accounts = "accept_deposits"
+//!
+//! [disputes]
+//! withdrawals = false
+//! allow_negative = false
+//! window_days = 120
+//! strict = true
+//!
+//! [interest]
+//! rate = "0.25"
+//! rounding = "half_up"
+//!
+//! [fees]
+//! house_account = 0
+//! withdrawal = { flat = "0.5" }
+//!
+//! [limits.default]
+//! max_withdrawal = "1000"
+//!
+//! [fraud.disputes]
+//! max = 3
+//! window_secs = 86400
+//! ```
+//!
+//! Every setting is optional, the defaults are the behaviour of a `Processor` without a
+//! configuration. The `fees`, `limits` and `fraud` tables have the same contents as the files of
+//! `FeeSchedule::load`, `Limits::load` and `FraudRules::load`.
+
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::amount::{Amount, Rounding};
+use crate::fees::FeeSchedule;
+use crate::fraud::FraudRules;
+use crate::limits::Limits;
+use crate::types::Error;
+
+const MAX_PRECISION: usize = 4;
+
+/// Which transactions can be disputed, and what disputes can do.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputePolicy {
+    /// Whether withdrawals and the sending side of transfers can be disputed, and not only
+    /// deposits and the receiving side of transfers
+    pub withdrawals: bool,
+    /// Whether a dispute can hold more than the available funds, leaving them negative
+    pub allow_negative: bool,
+    /// How many days after a transaction it can be disputed, see
+    /// `Processor::set_dispute_window`
+    pub window_days: Option<u64>,
+    /// Whether to reject disputes of transactions that are already disputed and chargebacks of
+    /// transactions that are not. Otherwise a second dispute holds the funds again (and a
+    /// resolve only releases them once), and any deposit, withdrawal or transfer can be charged
+    /// back.
+    pub strict: bool,
+}
+
+impl Default for DisputePolicy {
+    fn default() -> Self {
+        DisputePolicy {
+            withdrawals: true,
+            allow_negative: true,
+            window_days: None,
+            strict: false,
+        }
+    }
+}
+
+/// What happens to the transactions of a locked account.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockedPolicy {
+    /// Reject all of them
+    #[default]
+    Reject,
+    /// Still accept deposits, and reject everything else
+    AcceptDeposits,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InterestConfig {
+    /// Percentage of the available funds credited by `AccrueInterest`
+    pub rate: Amount,
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessorConfig {
+    /// Most decimals an amount can have, up to 4. Transactions with more are rejected, and fees
+    /// and interest credits are rounded to this many decimals.
+    pub precision: usize,
+    /// See `Processor::set_ordered_timestamps`
+    pub ordered_timestamps: bool,
+    /// See `Processor::set_wall_clock`
+    pub wall_clock: bool,
+    /// See `Processor::set_check_invariants`
+    pub check_invariants: bool,
+    pub disputes: DisputePolicy,
+    pub locked_accounts: LockedPolicy,
+    pub interest: Option<InterestConfig>,
+    pub fees: Option<FeeSchedule>,
+    pub limits: Option<Limits>,
+    pub fraud: Option<FraudRules>,
+}
+
+impl Default for ProcessorConfig {
+    fn default() -> Self {
+        ProcessorConfig {
+            precision: MAX_PRECISION,
+            ordered_timestamps: false,
+            wall_clock: false,
+            check_invariants: false,
+            disputes: DisputePolicy::default(),
+            locked_accounts: LockedPolicy::default(),
+            interest: None,
+            fees: None,
+            limits: None,
+            fraud: None,
+        }
+    }
+}
+
+impl ProcessorConfig {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let err = |e: String| Error::Deserialization(path.display().to_string(), e);
+        let s = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+        let config: ProcessorConfig = toml::from_str(&s).map_err(|e| err(e.to_string()))?;
+        config.validate().map_err(err)?;
+        Ok(config)
+    }
+
+    // Checks what the types alone can't
+    fn validate(&self) -> Result<(), String> {
+        if self.precision > MAX_PRECISION {
+            return Err(format!(
+                "precision is {}, but amounts can't have more than {} decimals",
+                self.precision, MAX_PRECISION
+            ));
+        }
+        if self.interest.as_ref().is_some_and(|i| i.rate < 0.into()) {
+            return Err("interest.rate can't be negative".to_string());
+        }
+        if self.disputes.window_days == Some(0) {
+            return Err("disputes.window_days must be at least 1".to_string());
+        }
+        if let Some(fees) = &self.fees {
+            fees.validate().map_err(|e| format!("fees: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+precision = 2
+locked_accounts = "accept_deposits"
+
+[disputes]
+withdrawals = false
+
+[interest]
+rate = "0.5"
+
+[fees]
+house_account = 9
+withdrawal = { flat = "1" }
+
+[limits.default]
+max_withdrawal = "10"
+"#,
+        )
+        .expect("Cannot write file");
+
+        let config = ProcessorConfig::load(&path).expect("Cannot load config");
+        assert_eq!(config.precision, 2);
+        assert!(!config.ordered_timestamps);
+        assert_eq!(
+            config.disputes,
+            DisputePolicy {
+                withdrawals: false,
+                ..DisputePolicy::default()
+            }
+        );
+        assert_eq!(config.locked_accounts, LockedPolicy::AcceptDeposits);
+        assert_eq!(
+            config.interest,
+            Some(InterestConfig {
+                rate: 5000.into(),
+                rounding: Rounding::HalfEven,
+            })
+        );
+        assert_eq!(config.fees.map(|f| f.house_account), Some(9));
+        assert!(config.limits.is_some());
+        assert!(config.fraud.is_none());
+
+        fs::write(&path, "").expect("Cannot write file");
+        let config = ProcessorConfig::load(&path).expect("Cannot load config");
+        assert_eq!(config.precision, 4);
+        assert_eq!(config.disputes, DisputePolicy::default());
+
+        for bad in [
+            "precision = 5",
+            "precsion = 2",
+            "locked_accounts = \"ignore\"",
+            "[interest]\nrate = \"-1\"",
+            "[disputes]\nwindow_days = 0",
+            "[limits.default]\nmax_txns = 1",
+            "[limits.default]\nmax_txns = 1\nwindow_secs = 0",
+            "[limits.default]\nmax_withdrawal = \"-1\"",
+            "[[limits.client]]\nclient = 1\nmax_daily_withdrawal = \"-1\"",
+            "[fees]\nhouse_account = 0\nwithdrawal = { flat = \"-0.5\" }",
+            "[fees]\nhouse_account = 0\ntransfer = { percent = \"-1\" }",
+            "[fees]\nhouse_account = 0\ndeposit = { min = \"2\", max = \"1\" }",
+        ] {
+            fs::write(&path, bad).expect("Cannot write file");
+            assert!(
+                matches!(
+                    ProcessorConfig::load(&path),
+                    Err(Error::Deserialization(..))
+                ),
+                "{}",
+                bad
+            );
+        }
+    }
+}
@@ -1,38 +1,84 @@
-use std::io::Write;
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+    sync::Arc,
+};
 
-use serde::{Deserialize, Serialize};
+use chrono::DateTime;
+use csv::StringRecord;
+use serde::Deserialize;
 
-use crate::types::{Account, ClientId, Error, Txn, TxnId};
+use crate::input::{Position, TxnSource};
+use crate::output::{AccountWriter, Output};
+use crate::types::{ClientId, Error, Timestamp, Txn, TxnId};
 
 // The csv crate does not support internally-tagged unions: https://github.com/BurntSushi/rust-csv/issues/211
 #[derive(Deserialize, Debug)]
 pub struct Input {
     #[serde(rename = "type")]
     tpe: String,
-    client: ClientId,
+    // Only missing in control rows
+    client: Option<ClientId>,
     tx: TxnId,
     amount: Option<String>,
+    // Only for transfers
+    to: Option<ClientId>,
+    timestamp: Option<RawTimestamp>,
 }
 
-#[derive(Serialize, Debug)]
-pub struct Output {
-    pub client: ClientId,
-    pub available: String,
-    pub held: String,
-    pub total: String,
-    pub locked: bool,
+// JSON inputs can have the seconds since the epoch as a number
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Secs(Timestamp),
+    Text(String),
+}
+
+/// Parses a timestamp, either in RFC 3339 format (e.g. `2024-01-02T03:04:05Z`) or as seconds
+/// since the epoch (e.g. `1704164645`). Fractions of a second are dropped.
+pub fn parse_timestamp(s: &str) -> Result<Timestamp, Error> {
+    let err = || Error::Input(format!("Invalid timestamp: {}", s));
+    if s.starts_with(|c: char| c.is_ascii_digit()) && !s.contains('-') {
+        let secs = s.split_once('.').map_or(s, |(secs, _)| secs);
+        return secs.parse().map_err(|_| err());
+    }
+    let t = DateTime::parse_from_rfc3339(s).map_err(|_| err())?;
+    t.timestamp().try_into().map_err(|_| err())
 }
 
 impl TryFrom<Input> for Txn {
     type Error = Error;
 
     fn try_from(inp: Input) -> Result<Txn, Self::Error> {
+        let timestamp = match inp.timestamp {
+            None => None,
+            Some(RawTimestamp::Secs(t)) => Some(t),
+            Some(RawTimestamp::Text(t)) if t.is_empty() => None,
+            Some(RawTimestamp::Text(t)) => Some(parse_timestamp(&t)?),
+        };
+        let client = match (inp.tpe.as_str(), inp.client) {
+            ("accrue_interest", _) => {
+                return Ok(Txn::AccrueInterest {
+                    tx: inp.tx,
+                    timestamp,
+                })
+            }
+            (_, Some(client)) => client,
+            (_, None) => {
+                return Err(Error::Input(format!(
+                    "Missing client in transaction {}",
+                    inp.tx
+                )))
+            }
+        };
         match inp.tpe.as_str() {
             "deposit" => match inp.amount {
                 Some(amt) => amt.try_into().map(|a| Txn::Deposit {
-                    client: inp.client,
+                    client,
                     tx: inp.tx,
                     amount: a,
+                    timestamp,
                 }),
                 None => Err(Error::Input(format!(
                     "Missing amount in transaction {}",
@@ -41,9 +87,10 @@ impl TryFrom<Input> for Txn {
             },
             "withdrawal" => match inp.amount {
                 Some(amt) => amt.try_into().map(|a| Txn::Withdrawal {
-                    client: inp.client,
+                    client,
                     tx: inp.tx,
                     amount: a,
+                    timestamp,
                 }),
                 None => Err(Error::Input(format!(
                     "Missing amount in transaction {}",
@@ -51,17 +98,41 @@ impl TryFrom<Input> for Txn {
                 ))),
             },
             "dispute" => Ok(Txn::Dispute {
-                client: inp.client,
+                client,
                 tx: inp.tx,
+                timestamp,
             }),
             "resolve" => Ok(Txn::Resolve {
-                client: inp.client,
+                client,
                 tx: inp.tx,
+                timestamp,
             }),
             "chargeback" => Ok(Txn::Chargeback {
-                client: inp.client,
+                client,
                 tx: inp.tx,
+                timestamp,
             }),
+            "transfer" => match (inp.amount, inp.to) {
+                (Some(amt), Some(to)) if to != client => amt.try_into().map(|a| Txn::Transfer {
+                    client,
+                    to,
+                    tx: inp.tx,
+                    amount: a,
+                    timestamp,
+                }),
+                (Some(_), Some(_)) => Err(Error::Input(format!(
+                    "Transfer to the same client in transaction {}",
+                    inp.tx
+                ))),
+                (None, _) => Err(Error::Input(format!(
+                    "Missing amount in transaction {}",
+                    inp.tx
+                ))),
+                (_, None) => Err(Error::Input(format!(
+                    "Missing destination client in transaction {}",
+                    inp.tx
+                ))),
+            },
             _ => Err(Error::Input(format!(
                 "Invalid transaction type in transaction {}",
                 inp.tx
@@ -70,140 +141,380 @@ impl TryFrom<Input> for Txn {
     }
 }
 
-impl From<&Account> for Output {
-    fn from(val: &Account) -> Self {
-        match val {
-            Account::Locked(a) => Output {
-                client: a.client,
-                available: (&a.available).into(),
-                held: (&a.held).into(),
-                total: (&(a.available + a.held)).into(),
-                locked: true,
-            },
-            Account::Unlocked(a) => Output {
-                client: a.client,
-                available: (&a.available).into(),
-                held: (&a.held).into(),
-                total: (&(a.available + a.held)).into(),
-                locked: false,
-            },
+pub struct CsvWriter<W: Write> {
+    wrt: csv::Writer<W>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W) -> Self {
+        CsvWriter {
+            wrt: csv::Writer::from_writer(writer),
         }
     }
 }
 
-pub fn save<'a, I: Iterator<Item = &'a Account>>(
-    writer: impl Write,
-    accts: I,
-) -> Result<(), Error> {
-    let out = accts.map(|a| -> Output { a.into() });
-    let mut wrt = csv::Writer::from_writer(writer);
-    for o in out {
-        if let Err(e) = wrt.serialize(o) {
-            return Err(Error::Serialization(e.to_string()));
-        }
+impl<W: Write> AccountWriter for CsvWriter<W> {
+    fn write(&mut self, out: &Output) -> Result<(), Error> {
+        self.wrt
+            .serialize(out)
+            .map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.wrt
+            .flush()
+            .map_err(|e| Error::Serialization(e.to_string()))
     }
-    Ok(())
 }
 
-pub fn process_csv<F>(path: String, mut f: F) -> Vec<Error>
-where
-    F: FnMut(&Txn) -> Result<(), Error>,
-{
-    let mut errs = Vec::new();
+/// Parses a single CSV row without a header, e.g. `deposit,1,2,3.0` or `transfer,1,2,3.0,4`.
+/// A timestamp can follow as a sixth column, e.g. `deposit,1,2,3.0,,1704164645`.
+pub fn parse_txn(line: &str) -> Result<Txn, Error> {
+    // Deserializing by position would require all fields to be present
+    parse_row(
+        line,
+        &StringRecord::from(vec!["type", "client", "tx", "amount", "to", "timestamp"]),
+    )
+}
 
-    match csv::ReaderBuilder::new()
+/// Parses a single CSV row whose columns are named by `headers`.
+pub fn parse_row(line: &str, headers: &StringRecord) -> Result<Txn, Error> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
         .flexible(true)
         .trim(csv::Trim::All)
-        .from_path(path.clone())
-    {
-        Ok(mut rdr) => {
-            for line in rdr.deserialize::<Input>() {
-                match line {
-                    Ok(i) => {
-                        if let Err(e) = i.try_into().and_then(|txn| f(&txn)) {
-                            errs.push(e);
-                        }
-                    }
-                    Err(e) => errs.push(Error::Deserialization(path.clone(), e.to_string())),
-                }
-            }
+        .from_reader(line.as_bytes());
+    let mut record = StringRecord::new();
+    match rdr.read_record(&mut record) {
+        Ok(true) => record
+            .deserialize::<Input>(Some(headers))
+            .map_err(|e| Error::Input(e.to_string()))
+            .and_then(Txn::try_from),
+        Ok(false) => Err(Error::Input("Empty transaction".to_string())),
+        Err(e) => Err(Error::Input(e.to_string())),
+    }
+}
+
+/// Renders an account as a CSV row without a header.
+pub fn to_row(out: &Output) -> Result<String, Error> {
+    to_csv(out, false)
+}
+
+/// Renders an account as a CSV row, preceded by the header if `headers` is set.
+pub fn to_csv(out: &Output, headers: bool) -> Result<String, Error> {
+    let mut wrt = csv::WriterBuilder::new()
+        .has_headers(headers)
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    wrt.serialize(out)
+        .map_err(|e| Error::Serialization(e.to_string()))?;
+    let bytes = wrt
+        .into_inner()
+        .map_err(|e| Error::Serialization(e.to_string()))?;
+    let row = String::from_utf8(bytes).map_err(|e| Error::Serialization(e.to_string()))?;
+    Ok(row.trim_end().to_string())
+}
+
+/// Reads transactions from a CSV file with a `type,client,tx,amount` header, plus a `to` column
+/// if there are transfers and a `timestamp` column if transactions have a time.
+pub struct CsvSource<R: Read> {
+    name: Arc<str>,
+    rdr: csv::Reader<R>,
+    headers: StringRecord,
+    record: StringRecord,
+}
+
+impl CsvSource<File> {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let name = path.display().to_string();
+        let file =
+            File::open(path).map_err(|e| Error::Deserialization(name.clone(), e.to_string()))?;
+        CsvSource::from_reader(name, file)
+    }
+}
+
+impl<R: Read> CsvSource<R> {
+    /// Creates a source reading from `reader`. `name` is only used to report errors and positions.
+    pub fn from_reader(name: String, reader: R) -> Result<Self, Error> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let headers = rdr
+            .headers()
+            .map_err(|e| Error::Deserialization(name.clone(), e.to_string()))?
+            .clone();
+
+        Ok(CsvSource {
+            name: name.into(),
+            rdr,
+            headers,
+            record: StringRecord::new(),
+        })
+    }
+}
+
+impl<R: Read> Iterator for CsvSource<R> {
+    type Item = Result<Txn, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rdr.read_record(&mut self.record) {
+            Ok(true) => Some(
+                self.record
+                    .deserialize::<Input>(Some(&self.headers))
+                    .map_err(|e| Error::Deserialization(self.name.to_string(), e.to_string()))
+                    .and_then(Txn::try_from),
+            ),
+            Ok(false) => None,
+            Err(e) => Some(Err(Error::Deserialization(
+                self.name.to_string(),
+                e.to_string(),
+            ))),
         }
-        Err(e) => errs.push(Error::Deserialization(path, e.to_string())),
     }
+}
 
-    errs
+impl<R: Read> TxnSource for CsvSource<R> {
+    fn last_position(&self) -> Position {
+        Position {
+            source: self.name.clone(),
+            line: self.record.position().map_or(0, |p| p.line()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::BufWriter;
-
-    use crate::types::AccountData;
+    use crate::types::{Account, AccountData};
 
     use super::*;
 
     #[test]
     fn test_deserialize_transaction() {
         let csv_str = r#"
-type,client,tx,amount
+type,client,tx,amount,to
 deposit,1,2,3
 withdrawal,1,2,3.5
 dispute,1,2,
 resolve,1,2
-chargeback,1,2"#;
+chargeback,1,2
+transfer,1,3,1,2"#;
 
-        let mut rdr = csv::ReaderBuilder::new()
-            .flexible(true)
-            .trim(csv::Trim::All)
-            .from_reader(csv_str.as_bytes());
-        let actual: Vec<Txn> = rdr
-            .deserialize::<Input>()
-            .map(|x| x.unwrap().try_into().unwrap())
-            .collect();
+        let src = CsvSource::from_reader("test".to_string(), csv_str.as_bytes())
+            .expect("Cannot read CSV");
+        let actual: Vec<Txn> = src.map(|x| x.unwrap()).collect();
         let expected = vec![
             Txn::Deposit {
                 client: 1,
                 tx: 2,
                 amount: 30000.into(),
+                timestamp: None,
             },
             Txn::Withdrawal {
                 client: 1,
                 tx: 2,
                 amount: 35000.into(),
+                timestamp: None,
+            },
+            Txn::Dispute {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            },
+            Txn::Resolve {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            },
+            Txn::Chargeback {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            },
+            Txn::Transfer {
+                client: 1,
+                to: 2,
+                tx: 3,
+                amount: 10000.into(),
+                timestamp: None,
             },
-            Txn::Dispute { client: 1, tx: 2 },
-            Txn::Resolve { client: 1, tx: 2 },
-            Txn::Chargeback { client: 1, tx: 2 },
         ];
 
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_parse_txn() {
+        assert_eq!(
+            parse_txn("deposit, 1, 2, 3.5"),
+            Ok(Txn::Deposit {
+                client: 1,
+                tx: 2,
+                amount: 35000.into(),
+                timestamp: None,
+            })
+        );
+        assert_eq!(
+            parse_txn("dispute,1,2"),
+            Ok(Txn::Dispute {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            })
+        );
+        assert_eq!(
+            parse_txn("resolve,1,2,"),
+            Ok(Txn::Resolve {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            })
+        );
+        assert_eq!(
+            parse_txn("transfer,1,2,3.5,4"),
+            Ok(Txn::Transfer {
+                client: 1,
+                to: 4,
+                tx: 2,
+                amount: 35000.into(),
+                timestamp: None,
+            })
+        );
+        assert_eq!(
+            parse_txn("accrue_interest,,7,"),
+            Ok(Txn::AccrueInterest {
+                tx: 7,
+                timestamp: None,
+            })
+        );
+        assert!(matches!(parse_txn("deposit,,2,3.5"), Err(Error::Input(_))));
+        assert!(matches!(
+            parse_txn("interest,1,2,3.5"),
+            Err(Error::Input(_))
+        ));
+        assert!(matches!(parse_txn("deposit,x,2,3.5"), Err(Error::Input(_))));
+        assert!(matches!(parse_txn(""), Err(Error::Input(_))));
+        assert!(matches!(
+            parse_txn("transfer,1,2,3.5"),
+            Err(Error::Input(_))
+        ));
+        assert!(matches!(
+            parse_txn("transfer,1,2,3.5,1"),
+            Err(Error::Input(_))
+        ));
+
+        // Transactions are displayed in the same format
+        for row in [
+            "withdrawal,1,2,3.5000",
+            "chargeback,1,2,",
+            "transfer,1,2,3.5000,4",
+            "accrue_interest,,2,",
+            "deposit,1,2,3.5000,,1704164645",
+            "transfer,1,2,3.5000,4,1704164645",
+        ] {
+            let txn = parse_txn(row).expect("Cannot parse");
+            assert_eq!(txn.to_string(), row);
+        }
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1704164645"), Ok(1704164645));
+        assert_eq!(parse_timestamp("1704164645.75"), Ok(1704164645));
+        assert_eq!(parse_timestamp("2024-01-02T03:04:05Z"), Ok(1704164645));
+        assert_eq!(
+            parse_timestamp("2024-01-02T05:04:05.5+02:00"),
+            Ok(1704164645)
+        );
+        for bad in [
+            "",
+            "yesterday",
+            "2024-01-02",
+            "1969-12-31T23:59:59Z",
+            "-1",
+            "1e9",
+        ] {
+            assert!(
+                matches!(parse_timestamp(bad), Err(Error::Input(_))),
+                "{}",
+                bad
+            );
+        }
+
+        assert_eq!(
+            parse_txn("dispute,1,2,,,2024-01-02T03:04:05Z"),
+            Ok(Txn::Dispute {
+                client: 1,
+                tx: 2,
+                timestamp: Some(1704164645),
+            })
+        );
+        assert!(matches!(
+            parse_txn("dispute,1,2,,,noon"),
+            Err(Error::Input(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_row() {
+        let acct = Account::Locked(AccountData {
+            client: 2,
+            available: 31111.into(),
+            held: 42222.into(),
+        });
+        assert_eq!(
+            to_row(&(&acct).into()),
+            Ok("2,3.1111,4.2222,7.3333,true".to_string())
+        );
+    }
+
+    #[test]
+    fn test_csv_source_errors_and_positions() {
+        let csv_str = r#"type,client,tx,amount
+deposit,1,1,1.0
+deposit,x,2,1.0
+deposit,1,3
+withdrawal,1,4,2.0"#;
+
+        let mut src = CsvSource::from_reader("test.csv".to_string(), csv_str.as_bytes())
+            .expect("Cannot read CSV");
+
+        let mut actual = Vec::new();
+        while let Some(item) = src.next() {
+            actual.push((src.last_position().to_string(), item.is_ok()));
+        }
+
+        let expected = vec![
+            ("test.csv:2".to_string(), true),
+            ("test.csv:3".to_string(), false),
+            ("test.csv:4".to_string(), false),
+            ("test.csv:5".to_string(), true),
+        ];
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn test_serialize_accounts() {
-        let accts: Vec<Output> = vec![
-            (&Account::Unlocked(AccountData {
+        let accts = vec![
+            Account::Unlocked(AccountData {
                 client: 1,
                 available: 30000.into(),
                 held: 40000.into(),
-            }))
-                .into(),
-            (&Account::Locked(AccountData {
+            }),
+            Account::Locked(AccountData {
                 client: 2,
                 available: 31111.into(),
                 held: 42222.into(),
-            }))
-                .into(),
+            }),
         ];
 
-        let buf = BufWriter::new(Vec::new());
-        let mut wrt = csv::Writer::from_writer(buf);
-        for acct in accts {
-            wrt.serialize(acct).expect("Cannot serialize");
+        let mut buf = Vec::new();
+        let mut wrt = Box::new(CsvWriter::new(&mut buf));
+        for acct in &accts {
+            wrt.write(&acct.into()).expect("Cannot serialize");
         }
-        wrt.flush().expect("Cannot flush");
-        let bytes = wrt.into_inner().expect("").into_inner().expect("");
-        let actual = String::from_utf8(bytes).expect("Invalid utf8");
+        wrt.finish().expect("Cannot flush");
+        let actual = String::from_utf8(buf).expect("Invalid utf8");
 
         let expected = r#"client,available,held,total,locked
 1,3.0000,4.0000,7.0000,false
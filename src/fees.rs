@@ -0,0 +1,195 @@
+//! Fees charged on transactions, configured in a TOML file like:
+//!
+//! ```toml
+//! house_account = 0
+//! rounding = "half_even"
+//!
+//! [withdrawal]
+//! flat = "0.5"
+//! percent = "1"
+//! min = "1"
+//! max = "10"
+//!
+//! [chargeback]
+//! flat = "15"
+//! ```
+//!
+//! Amounts are strings so that they are not subject to floating point errors.
+
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::amount::{Amount, Rounding};
+use crate::types::{ClientId, Error, Txn};
+
+/// The fee for one type of transaction: `flat + percent% * amount`, then limited to `min` and
+/// `max` if they are set.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeRule {
+    #[serde(default)]
+    pub flat: Amount,
+    #[serde(default)]
+    pub percent: Amount,
+    pub min: Option<Amount>,
+    pub max: Option<Amount>,
+}
+
+impl FeeRule {
+    /// The fee on `amount`, rounded to `decimals` decimals.
+    pub fn fee(&self, amount: Amount, decimals: usize, rounding: Rounding) -> Amount {
+        let mut fee = self.flat + amount.percent(self.percent, decimals, rounding);
+        if let Some(min) = self.min {
+            fee = fee.max(min);
+        }
+        if let Some(max) = self.max {
+            fee = fee.min(max);
+        }
+        // Only changes anything if the other settings have more decimals
+        fee.round(decimals, rounding)
+    }
+
+    // Checks what the types alone can't
+    fn validate(&self) -> Result<(), String> {
+        let amounts = [Some(self.flat), Some(self.percent), self.min, self.max];
+        if amounts.into_iter().flatten().any(|a| a < 0.into()) {
+            return Err("amounts can't be negative".to_string());
+        }
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if min > max => Err(format!("min {} is above max {}", min, max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Which transactions are charged a fee, and how much. All fees are paid by the client of the
+/// transaction (the sender for transfers) and credited to the house account.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeSchedule {
+    pub house_account: ClientId,
+    #[serde(default)]
+    pub rounding: Rounding,
+    pub deposit: Option<FeeRule>,
+    pub withdrawal: Option<FeeRule>,
+    pub transfer: Option<FeeRule>,
+    /// Charged on the amount of the transaction that was charged back
+    pub chargeback: Option<FeeRule>,
+}
+
+impl FeeSchedule {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let err = |e: String| Error::Deserialization(path.display().to_string(), e);
+        let s = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+        let fees: FeeSchedule = toml::from_str(&s).map_err(|e| err(e.to_string()))?;
+        fees.validate().map_err(err)?;
+        Ok(fees)
+    }
+
+    /// Checks that the fees of every rule are well defined.
+    pub fn validate(&self) -> Result<(), String> {
+        let rules = [
+            ("deposit", &self.deposit),
+            ("withdrawal", &self.withdrawal),
+            ("transfer", &self.transfer),
+            ("chargeback", &self.chargeback),
+        ];
+        for (name, rule) in rules {
+            if let Some(rule) = rule {
+                rule.validate()
+                    .map_err(|e| format!("{} fee: {}", name, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The fee for `txn`, where `amount` is the amount it moves, rounded to `decimals` decimals.
+    /// The house account doesn't pay fees to itself.
+    pub fn fee(&self, txn: &Txn, amount: Amount, decimals: usize) -> Amount {
+        let rule = match txn {
+            _ if txn.client() == self.house_account => None,
+            Txn::Deposit { .. } => self.deposit.as_ref(),
+            Txn::Withdrawal { .. } => self.withdrawal.as_ref(),
+            Txn::Transfer { .. } => self.transfer.as_ref(),
+            Txn::Chargeback { .. } => self.chargeback.as_ref(),
+            _ => None,
+        };
+        rule.map_or(Amount::default(), |r| {
+            r.fee(amount, decimals, self.rounding)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("fees.toml");
+        fs::write(
+            &path,
+            r#"
+house_account = 9
+rounding = "up"
+
+[withdrawal]
+flat = "0.5"
+percent = "1"
+min = "1"
+max = "10"
+"#,
+        )
+        .expect("Cannot write file");
+
+        let fees = FeeSchedule::load(&path).expect("Cannot load fees");
+        assert_eq!(fees.house_account, 9);
+        assert_eq!(fees.rounding, Rounding::Up);
+        assert_eq!(fees.deposit, None);
+
+        let withdrawal = |client, amount: i64| Txn::Withdrawal {
+            client,
+            tx: 1,
+            amount: amount.into(),
+            timestamp: None,
+        };
+        // 0.5 + 1% of 2, raised to the minimum
+        assert_eq!(
+            fees.fee(&withdrawal(1, 20000), 20000.into(), 4),
+            10000.into()
+        );
+        // 0.5 + 1% of 100.0001, rounded up
+        assert_eq!(
+            fees.fee(&withdrawal(1, 1000001), 1000001.into(), 4),
+            15001.into()
+        );
+        assert_eq!(
+            fees.fee(&withdrawal(1, 1 << 40), (1 << 40).into(), 4),
+            100000.into()
+        );
+        assert_eq!(fees.fee(&withdrawal(9, 20000), 20000.into(), 4), 0.into());
+
+        for bad in [
+            "house_account = 9\n[withdrawal]\nflat = 0.5\n",
+            "house_account = 9\n[withdrawal]\nflat = \"-0.5\"\n",
+            "house_account = 9\n[chargeback]\nmin = \"10\"\nmax = \"1\"\n",
+        ] {
+            fs::write(&path, bad).expect("Cannot write");
+            assert!(matches!(
+                FeeSchedule::load(&path),
+                Err(Error::Deserialization(..))
+            ));
+        }
+        assert_eq!(
+            FeeRule {
+                min: Some(10.into()),
+                max: Some(1.into()),
+                ..Default::default()
+            }
+            .validate(),
+            Err("min 0.0010 is above max 0.0001".to_string())
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Follows a file that keeps growing, like `tail -f`, processing new transactions as they are
+//! appended.
+
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use csv::StringRecord;
+
+use crate::csv_utils;
+use crate::input::{InputFormat, Position};
+use crate::json_utils;
+use crate::processor::Processor;
+use crate::types::{Error, Txn};
+
+/// Reads the transactions appended to a file since the last call to `poll`.
+///
+/// Only complete lines are processed: a partially written row is kept until the rest of it
+/// arrives. If the file is truncated it is read again from the start, and if it is replaced
+/// (e.g. by log rotation) the rest of the old file is read before switching to the new one. In
+/// both cases a CSV file is expected to start with a header again.
+pub struct Follower {
+    path: PathBuf,
+    name: Arc<str>,
+    format: InputFormat,
+    file: Option<File>,
+    // Bytes of the current file consumed so far, up to the last complete line: `partial` comes
+    // right after them
+    offset: u64,
+    partial: Vec<u8>,
+    line: u64,
+    headers: Option<StringRecord>,
+}
+
+impl Follower {
+    /// Creates a follower for `path`, which doesn't need to exist yet. `format` must not be
+    /// `Auto`, since an empty file can't be sniffed.
+    pub fn new(path: &Path, format: InputFormat) -> Self {
+        Follower {
+            path: path.to_path_buf(),
+            name: path.display().to_string().into(),
+            format,
+            file: None,
+            offset: 0,
+            partial: Vec::new(),
+            line: 0,
+            headers: None,
+        }
+    }
+
+    /// Processes all complete lines appended since the last call, returning how many
+    /// transactions were read (whether they could be applied or not). `on_error` is called for
+    /// each of them that couldn't.
+    pub fn poll<F>(&mut self, p: &mut Processor, mut on_error: F) -> Result<usize, Error>
+    where
+        F: FnMut(&Position, Error),
+    {
+        let name = self.name.clone();
+        let err = |e: std::io::Error| Error::Deserialization(name.to_string(), e.to_string());
+
+        let mut count = 0;
+        if self.file.is_none() {
+            match File::open(&self.path) {
+                Ok(f) => self.reset(f),
+                // It may not have been created yet, or be in the middle of a rotation
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+                Err(e) => return Err(err(e)),
+            }
+        }
+
+        let current = self.file.as_ref().map(|f| f.metadata()).transpose();
+        let current = current.map_err(err)?;
+        match fs::metadata(&self.path) {
+            Ok(meta) if is_replaced(current.as_ref(), &meta) => {
+                // Finish the old file, then start over with the new one
+                count += self.read_available(p, &mut on_error)?;
+                if self.flush_partial(p, &mut on_error) {
+                    count += 1;
+                }
+                let f = File::open(&self.path).map_err(err)?;
+                self.reset(f);
+            }
+            Ok(meta) if meta.len() < self.offset => {
+                let f = File::open(&self.path).map_err(err)?;
+                self.reset(f);
+            }
+            _ => {}
+        }
+
+        count += self.read_available(p, &mut on_error)?;
+        Ok(count)
+    }
+
+    fn reset(&mut self, file: File) {
+        self.file = Some(file);
+        self.offset = 0;
+        self.partial.clear();
+        self.line = 0;
+        self.headers = None;
+    }
+
+    fn read_available<F>(&mut self, p: &mut Processor, on_error: &mut F) -> Result<usize, Error>
+    where
+        F: FnMut(&Position, Error),
+    {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(0);
+        };
+
+        let mut buf = Vec::new();
+        file.seek(SeekFrom::Start(self.offset + self.partial.len() as u64))
+            .and_then(|_| file.read_to_end(&mut buf))
+            .map_err(|e| Error::Deserialization(self.name.to_string(), e.to_string()))?;
+
+        let mut count = 0;
+        self.partial.extend_from_slice(&buf);
+        while let Some(end) = self.partial.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            self.offset += line.len() as u64;
+            if self.process_line(&line, p, on_error) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    // A rotated file won't grow any more, so its last line counts even without a newline
+    fn flush_partial<F>(&mut self, p: &mut Processor, on_error: &mut F) -> bool
+    where
+        F: FnMut(&Position, Error),
+    {
+        let line = std::mem::take(&mut self.partial);
+        self.offset += line.len() as u64;
+        self.process_line(&line, p, on_error)
+    }
+
+    // Returns whether the line contained a transaction
+    fn process_line<F>(&mut self, line: &[u8], p: &mut Processor, on_error: &mut F) -> bool
+    where
+        F: FnMut(&Position, Error),
+    {
+        self.line += 1;
+        let pos = Position {
+            source: self.name.clone(),
+            line: self.line,
+        };
+
+        let line = match std::str::from_utf8(line.trim_ascii()) {
+            Ok("") => return false,
+            Ok(l) => l,
+            Err(e) => {
+                on_error(&pos, Error::Input(e.to_string()));
+                return true;
+            }
+        };
+
+        let txn = match self.format {
+            InputFormat::Json => json_utils::parse_txn(line),
+            _ => match &self.headers {
+                Some(headers) => csv_utils::parse_row(line, headers),
+                None => {
+                    self.headers = Some(line.split(',').map(str::trim).collect());
+                    return false;
+                }
+            },
+        };
+
+        if let Err(e) = txn.and_then(|t: Txn| p.process_txn(&t)) {
+            on_error(&pos, e);
+        }
+        true
+    }
+}
+
+#[cfg(unix)]
+fn is_replaced(current: Option<&fs::Metadata>, new: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    current.is_some_and(|c| (c.dev(), c.ino()) != (new.dev(), new.ino()))
+}
+
+// Without inodes only truncation can be detected
+#[cfg(not(unix))]
+fn is_replaced(_current: Option<&fs::Metadata>, _new: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use crate::amount::Amount;
+    use crate::types::Account;
+
+    use super::*;
+
+    fn append(path: &Path, data: &str) {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .expect("Cannot open file");
+        f.write_all(data.as_bytes()).expect("Cannot write file");
+    }
+
+    fn available(p: &Processor, client: u16) -> Amount {
+        match p.get_account(client).expect("No account") {
+            Account::Locked(d) | Account::Unlocked(d) => d.available,
+        }
+    }
+
+    #[test]
+    fn test_follow() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("txns.csv");
+        let mut f = Follower::new(&path, InputFormat::Csv);
+        let mut p = Processor::default();
+        let mut errs = Vec::new();
+        let mut poll = |f: &mut Follower, p: &mut Processor| {
+            f.poll(p, |pos, e| errs.push((pos.line, e)))
+                .expect("Error polling")
+        };
+
+        // Not created yet
+        assert_eq!(poll(&mut f, &mut p), 0);
+
+        append(
+            &path,
+            "type, client, tx, amount\ndeposit, 1, 1, 10\nwithdrawal,1,2,",
+        );
+        assert_eq!(poll(&mut f, &mut p), 1);
+        append(&path, "3\n\nwithdrawal,1,3,100\n");
+        assert_eq!(poll(&mut f, &mut p), 2);
+        assert_eq!(poll(&mut f, &mut p), 0);
+        assert_eq!(available(&p, 1), Amount::from(70000));
+
+        // Truncated and written again, with a different header
+        fs::write(&path, "client,type,tx,amount\n2,deposit,4,1\n").expect("Cannot write");
+        assert_eq!(poll(&mut f, &mut p), 1);
+        assert_eq!(available(&p, 2), Amount::from(10000));
+
+        // Rotated: the rest of the old file is read first
+        let old = dir.path().join("txns.csv.1");
+        fs::rename(&path, &old).expect("Cannot rename");
+        append(&old, "2,deposit,5,1");
+        append(&path, "type,client,tx,amount\ndeposit,3,6,1\n");
+        assert_eq!(poll(&mut f, &mut p), 2);
+        assert_eq!(available(&p, 2), Amount::from(20000));
+        assert_eq!(available(&p, 3), Amount::from(10000));
+
+        assert_eq!(errs, vec![(5, Error::InsufficientFunds(3))]);
+    }
+
+    #[test]
+    fn test_follow_json() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("txns.jsonl");
+        let mut f = Follower::new(&path, InputFormat::Json);
+        let mut p = Processor::default();
+
+        append(
+            &path,
+            "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2\"}\n{\"type\":",
+        );
+        assert_eq!(f.poll(&mut p, |_, e| panic!("{}", e)), Ok(1));
+        append(
+            &path,
+            "\"deposit\",\"client\":1,\"tx\":2,\"amount\":\"3\"}\n",
+        );
+        assert_eq!(f.poll(&mut p, |_, e| panic!("{}", e)), Ok(1));
+        assert_eq!(available(&p, 1), Amount::from(50000));
+    }
+}
@@ -0,0 +1,325 @@
+//! Rules that flag suspicious activity, configured in a TOML file like:
+//!
+//! ```toml
+//! # Lock the accounts that trigger an alert
+//! lock = true
+//!
+//! # More than 3 disputes in a day
+//! [disputes]
+//! max = 3
+//! window_secs = 86400
+//!
+//! # A withdrawal or transfer less than an hour after a deposit of at least 1000
+//! [withdrawal_after_deposit]
+//! min_deposit = "1000"
+//! within_secs = 3600
+//!
+//! # Chargebacks for more than 10% of the deposits, withdrawals and transfers, once there are 5
+//! [chargeback_ratio]
+//! max_percent = "10"
+//! min_txns = 5
+//! ```
+//!
+//! Rules only look at the transactions that were applied, and every transaction that breaks one
+//! raises an `Alert`.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    io::Write,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+use crate::types::{ClientId, Error, Timestamp, Txn, TxnId};
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisputeRule {
+    pub max: usize,
+    pub window_secs: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DepositRule {
+    pub min_deposit: Amount,
+    pub within_secs: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChargebackRule {
+    pub max_percent: Amount,
+    /// Don't judge clients with fewer deposits, withdrawals and transfers than this
+    #[serde(default)]
+    pub min_txns: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FraudRules {
+    /// Whether to lock the accounts that raise an alert
+    #[serde(default)]
+    pub lock: bool,
+    pub disputes: Option<DisputeRule>,
+    pub withdrawal_after_deposit: Option<DepositRule>,
+    pub chargeback_ratio: Option<ChargebackRule>,
+}
+
+impl FraudRules {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let err = |e: String| Error::Deserialization(path.display().to_string(), e);
+        let s = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+        toml::from_str(&s).map_err(|e| err(e.to_string()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    Disputes,
+    WithdrawalAfterDeposit,
+    ChargebackRatio,
+}
+
+/// A transaction that broke a rule, as it is written in the flagged activity report.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub client: ClientId,
+    pub tx: TxnId,
+    pub rule: Rule,
+    /// When the transaction happened, in seconds since the epoch, if it's known
+    pub time: Option<Timestamp>,
+}
+
+// What the rules need to know about the past of each client
+#[derive(Clone, Debug, Default)]
+struct Activity {
+    // Times of the disputes still within the window
+    disputes: VecDeque<Timestamp>,
+    // Time of the latest deposit of at least `min_deposit`
+    large_deposit: Option<Timestamp>,
+    // Deposits, withdrawals and transfers sent
+    txns: u64,
+    chargebacks: u64,
+}
+
+/// The rules, and the alerts they have raised so far.
+#[derive(Clone, Debug, Default)]
+pub struct Fraud {
+    rules: FraudRules,
+    activity: HashMap<ClientId, Activity>,
+    alerts: Vec<Alert>,
+}
+
+impl Fraud {
+    pub fn new(rules: FraudRules) -> Fraud {
+        Fraud {
+            rules,
+            activity: HashMap::new(),
+            alerts: Vec::new(),
+        }
+    }
+
+    /// Whether accounts that raise an alert must be locked.
+    pub fn locks(&self) -> bool {
+        self.rules.lock
+    }
+
+    /// Evaluates `txn`, which was applied at time `now`, against all rules. Returns whether it
+    /// raised any alert. Without a time only the rules that don't depend on it are evaluated.
+    pub fn check(&mut self, txn: &Txn, now: Option<Timestamp>) -> bool {
+        let activity = self.activity.entry(txn.client()).or_default();
+        let mut broken = Vec::new();
+
+        match txn {
+            Txn::Deposit { amount, .. } => {
+                activity.txns += 1;
+                if let (Some(rule), Some(now)) = (&self.rules.withdrawal_after_deposit, now) {
+                    if *amount >= rule.min_deposit {
+                        activity.large_deposit = Some(now);
+                    }
+                }
+            }
+            Txn::Withdrawal { .. } | Txn::Transfer { .. } => {
+                activity.txns += 1;
+                if let (Some(rule), Some(t), Some(now)) = (
+                    &self.rules.withdrawal_after_deposit,
+                    activity.large_deposit,
+                    now,
+                ) {
+                    if now < t.saturating_add(rule.within_secs) {
+                        broken.push(Rule::WithdrawalAfterDeposit);
+                    }
+                }
+            }
+            Txn::Dispute { .. } => {
+                if let (Some(rule), Some(now)) = (&self.rules.disputes, now) {
+                    activity.disputes.push_back(now);
+                    while activity
+                        .disputes
+                        .front()
+                        .is_some_and(|t| t.saturating_add(rule.window_secs) <= now)
+                    {
+                        activity.disputes.pop_front();
+                    }
+                    if activity.disputes.len() > rule.max {
+                        broken.push(Rule::Disputes);
+                    }
+                }
+            }
+            Txn::Chargeback { .. } => {
+                activity.chargebacks += 1;
+                if let Some(rule) = &self.rules.chargeback_ratio {
+                    let txns = activity.txns.max(1);
+                    let ratio = Amount::from((activity.chargebacks * 100 * 10000 / txns) as i64);
+                    if activity.txns >= rule.min_txns && ratio > rule.max_percent {
+                        broken.push(Rule::ChargebackRatio);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        let flagged = !broken.is_empty();
+        self.alerts.extend(broken.into_iter().map(|rule| Alert {
+            client: txn.client(),
+            tx: txn.tx(),
+            rule,
+            time: now,
+        }));
+        flagged
+    }
+
+    /// All alerts raised so far, in the order they were raised.
+    pub fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    /// Forgets all activity and alerts, but keeps the rules.
+    pub fn clear(&mut self) {
+        self.activity.clear();
+        self.alerts.clear();
+    }
+
+    /// Adds the activity and alerts of `other`, which must have seen other clients.
+    pub(crate) fn merge(&mut self, other: Fraud) {
+        self.activity.extend(other.activity);
+        self.alerts.extend(other.alerts);
+    }
+}
+
+/// Writes `alerts` as a CSV report with a `client,tx,rule,time` header.
+pub fn save_alerts<'a, W, I>(writer: W, alerts: I) -> Result<(), Error>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a Alert>,
+{
+    let err = |e: csv::Error| Error::Serialization(e.to_string());
+    let mut wrt = csv::Writer::from_writer(writer);
+    for a in alerts {
+        wrt.serialize(a).map_err(err)?;
+    }
+    wrt.flush().map_err(|e| Error::Serialization(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        let rules: FraudRules = toml::from_str(
+            r#"
+[disputes]
+max = 1
+window_secs = 100
+
+[withdrawal_after_deposit]
+min_deposit = "50"
+within_secs = 10
+
+[chargeback_ratio]
+max_percent = "20"
+min_txns = 3
+"#,
+        )
+        .expect("Cannot parse rules");
+        let mut fraud = Fraud::new(rules.clone());
+
+        let deposit = |tx, amount: i64| Txn::Deposit {
+            client: 1,
+            tx,
+            amount: amount.into(),
+            timestamp: None,
+        };
+        let withdrawal = |tx| Txn::Withdrawal {
+            client: 1,
+            tx,
+            amount: 10000.into(),
+            timestamp: None,
+        };
+        let dispute = |tx| Txn::Dispute {
+            client: 1,
+            tx,
+            timestamp: None,
+        };
+        let chargeback = |tx| Txn::Chargeback {
+            client: 1,
+            tx,
+            timestamp: None,
+        };
+
+        let txs = [
+            (deposit(1, 490000), 0, false),
+            (withdrawal(2), 1, false),
+            (deposit(3, 500000), 100, false),
+            (withdrawal(4), 109, true),
+            (withdrawal(5), 110, false),
+            (dispute(1), 200, false),
+            (dispute(2), 299, true),
+            // The first one is out of the window now
+            (dispute(3), 399, false),
+            // 1 in 5
+            (chargeback(1), 400, false),
+            // 2 in 5
+            (chargeback(2), 400, true),
+        ];
+        for (txn, now, flagged) in txs {
+            assert_eq!(fraud.check(&txn, Some(now)), flagged, "{}", txn);
+        }
+
+        let mut buf = Vec::new();
+        save_alerts(&mut buf, fraud.alerts()).expect("Cannot save alerts");
+        assert_eq!(
+            String::from_utf8(buf).expect("Invalid UTF-8"),
+            "client,tx,rule,time\n\
+             1,4,withdrawal_after_deposit,109\n\
+             1,2,disputes,299\n\
+             1,2,chargeback_ratio,400\n"
+        );
+
+        // Without a time only the chargeback ratio is evaluated
+        let mut fraud = Fraud::new(rules);
+        let txs = [
+            (deposit(1, 500000), false),
+            (withdrawal(2), false),
+            (deposit(3, 10), false),
+            (dispute(1), false),
+            (dispute(2), false),
+            (chargeback(1), true),
+        ];
+        for (txn, flagged) in txs {
+            assert_eq!(fraud.check(&txn, None), flagged, "{}", txn);
+        }
+        let mut buf = Vec::new();
+        save_alerts(&mut buf, fraud.alerts()).expect("Cannot save alerts");
+        assert_eq!(
+            String::from_utf8(buf).expect("Invalid UTF-8"),
+            "client,tx,rule,time\n1,1,chargeback_ratio,\n"
+        );
+    }
+}
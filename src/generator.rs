@@ -0,0 +1,413 @@
+//! Synthetic transaction streams, for testing and benchmarking with realistic inputs.
+//!
+//! Most rows are deposits and withdrawals of random clients. Disputes mostly refer to earlier
+//! deposits, withdrawals and transfers of the same client, and the rest to random transactions,
+//! like the bogus disputes real inputs have. Some disputes are resolved or charged back a few
+//! rows later. The output only depends on the configuration, including the seed.
+
+use std::io::Write;
+
+use crate::amount::Amount;
+use crate::input::InputFormat;
+use crate::types::{ClientId, Error, Txn, TxnId};
+
+// Share of the disputes that refer to a random transaction
+const BOGUS: f64 = 0.1;
+
+/// A small xorshift generator, good enough to produce reproducible data.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // Scrambled with splitmix64, since xorshift gets stuck at 0 and similar seeds would
+        // start with similar numbers
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        Rng((z ^ (z >> 31)).max(1))
+    }
+
+    /// A number in `0..n`, which must not be 0.
+    pub fn next(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    /// A number in `0.0..1.0`.
+    pub fn fraction(&mut self) -> f64 {
+        const PRECISION: u64 = 1 << 53;
+        self.next(PRECISION) as f64 / PRECISION as f64
+    }
+}
+
+/// What to generate. The shares are of the number of rows unless said otherwise, and deposits
+/// make up the rest.
+#[derive(Clone, Debug)]
+pub struct GeneratorConfig {
+    pub clients: ClientId,
+    pub count: u32,
+    pub withdrawals: f64,
+    pub transfers: f64,
+    pub disputes: f64,
+    /// Share of the disputes that are resolved
+    pub resolves: f64,
+    /// Share of the disputes that are charged back
+    pub chargebacks: f64,
+    /// Rows that can't be parsed, see `Malformed`
+    pub malformed: f64,
+    pub seed: u64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            clients: 10_000,
+            count: 100_000,
+            withdrawals: 0.3,
+            transfers: 0.05,
+            disputes: 0.01,
+            resolves: 0.7,
+            chargebacks: 0.02,
+            malformed: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    pub fn validate(&self) -> Result<(), Error> {
+        let err = |e: &str| Err(Error::Input(e.to_string()));
+        if self.clients == 0 {
+            return err("There must be at least one client");
+        }
+        let shares = [
+            self.withdrawals,
+            self.transfers,
+            self.disputes,
+            self.resolves,
+            self.chargebacks,
+            self.malformed,
+        ];
+        if shares.iter().any(|s| !(0.0..=1.0).contains(s)) {
+            return err("Shares must be between 0 and 1");
+        }
+        if self.resolves + self.chargebacks > 1.0 {
+            return err("Resolves and chargebacks can't add up to more than all disputes");
+        }
+        let rows = self.withdrawals
+            + self.transfers
+            + self.disputes * (1.0 + self.resolves + self.chargebacks)
+            + self.malformed;
+        if rows > 1.0 {
+            return err("Shares can't add up to more than 1");
+        }
+        Ok(())
+    }
+}
+
+/// How a malformed row is broken.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Malformed {
+    UnknownType,
+    MissingAmount,
+    InvalidAmount,
+    /// Cut short after the client
+    Truncated,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Row {
+    Txn(Txn),
+    Malformed {
+        kind: Malformed,
+        client: ClientId,
+        tx: TxnId,
+    },
+}
+
+/// Generates the rows of a `GeneratorConfig`.
+pub struct Generator {
+    config: GeneratorConfig,
+    // Where the share of each kind of row ends, from 0 to 1: malformed rows, disputes, resolves
+    // and chargebacks, withdrawals and transfers
+    ends: [f64; 5],
+    rng: Rng,
+    rows: u32,
+    // The id of the next deposit, withdrawal or transfer
+    next_tx: TxnId,
+    // Deposits, withdrawals and transfers, which can be disputed
+    history: Vec<(ClientId, TxnId)>,
+    // Resolves and chargebacks of earlier disputes
+    pending: Vec<Txn>,
+}
+
+impl Generator {
+    pub fn new(config: GeneratorConfig) -> Generator {
+        let c = &config;
+        let mut ends = [
+            c.malformed,
+            c.disputes,
+            c.disputes * (c.resolves + c.chargebacks),
+            c.withdrawals,
+            c.transfers,
+        ];
+        for i in 1..ends.len() {
+            ends[i] += ends[i - 1];
+        }
+        Generator {
+            ends,
+            rng: Rng::new(config.seed),
+            config,
+            rows: 0,
+            next_tx: 1,
+            history: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn client(&mut self) -> ClientId {
+        self.rng.next(self.config.clients as u64) as ClientId + 1
+    }
+
+    // A whole number of cents up to `max`
+    fn amount(&mut self, max: u64) -> Amount {
+        ((self.rng.next(max * 100) as i64 + 1) * 100).into()
+    }
+
+    fn tx(&mut self) -> TxnId {
+        let tx = self.next_tx;
+        self.next_tx = self.next_tx.saturating_add(1);
+        tx
+    }
+
+    // An earlier transaction to dispute, or a random one
+    fn dispute(&mut self) -> Txn {
+        let len = self.history.len() as u64;
+        let (client, tx) = if len > 0 && self.rng.fraction() >= BOGUS {
+            self.history[self.rng.next(len) as usize]
+        } else {
+            let client = self.client();
+            (client, self.rng.next(self.next_tx as u64) as TxnId + 1)
+        };
+
+        let timestamp = None;
+        let r = self.rng.fraction();
+        if r < self.config.resolves {
+            self.pending.push(Txn::Resolve {
+                client,
+                tx,
+                timestamp,
+            });
+        } else if r < self.config.resolves + self.config.chargebacks {
+            self.pending.push(Txn::Chargeback {
+                client,
+                tx,
+                timestamp,
+            });
+        }
+        Txn::Dispute {
+            client,
+            tx,
+            timestamp,
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        if self.rows >= self.config.count {
+            return None;
+        }
+        self.rows += 1;
+
+        let ends = self.ends;
+        // A resolve or chargeback needs an earlier dispute, otherwise the row is drawn again so
+        // that it's as likely to be anything else as usual
+        let r = loop {
+            let r = self.rng.fraction();
+            if !(ends[1]..ends[2]).contains(&r) || !self.pending.is_empty() {
+                break r;
+            }
+        };
+        let timestamp = None;
+
+        let row = match r {
+            _ if r < ends[0] => {
+                let kind = match self.rng.next(4) {
+                    0 => Malformed::UnknownType,
+                    1 => Malformed::MissingAmount,
+                    2 => Malformed::InvalidAmount,
+                    _ => Malformed::Truncated,
+                };
+                let client = self.client();
+                let tx = self.tx();
+                Row::Malformed { kind, client, tx }
+            }
+            _ if r < ends[1] => Row::Txn(self.dispute()),
+            _ if r < ends[2] => {
+                let i = self.rng.next(self.pending.len() as u64) as usize;
+                Row::Txn(self.pending.swap_remove(i))
+            }
+            _ if r < ends[3] => {
+                let client = self.client();
+                let tx = self.tx();
+                self.history.push((client, tx));
+                Row::Txn(Txn::Withdrawal {
+                    client,
+                    tx,
+                    amount: self.amount(2000),
+                    timestamp,
+                })
+            }
+            _ if r < ends[4] && self.config.clients > 1 => {
+                let client = self.client();
+                // Any other client
+                let clients = self.config.clients as u64;
+                let to = ((client as u64 + self.rng.next(clients - 1)) % clients + 1) as ClientId;
+                let tx = self.tx();
+                self.history.push((client, tx));
+                Row::Txn(Txn::Transfer {
+                    client,
+                    to,
+                    tx,
+                    amount: self.amount(1000),
+                    timestamp,
+                })
+            }
+            _ => {
+                let client = self.client();
+                let tx = self.tx();
+                self.history.push((client, tx));
+                Row::Txn(Txn::Deposit {
+                    client,
+                    tx,
+                    amount: self.amount(10000),
+                    timestamp,
+                })
+            }
+        };
+        Some(row)
+    }
+}
+
+fn csv_row(row: &Row) -> String {
+    match row {
+        Row::Txn(txn) => txn.to_string(),
+        Row::Malformed { kind, client, tx } => match kind {
+            Malformed::UnknownType => format!("refund,{},{},1.0", client, tx),
+            Malformed::MissingAmount => format!("deposit,{},{},", client, tx),
+            Malformed::InvalidAmount => format!("deposit,{},{},1.0.0", client, tx),
+            Malformed::Truncated => format!("deposit,{}", client),
+        },
+    }
+}
+
+fn json_row(row: &Row) -> String {
+    match row {
+        Row::Txn(txn) => crate::json_utils::to_json(txn),
+        Row::Malformed { kind, client, tx } => match kind {
+            Malformed::UnknownType => format!(
+                r#"{{"type":"refund","client":{},"tx":{},"amount":"1.0"}}"#,
+                client, tx
+            ),
+            Malformed::MissingAmount => {
+                format!(r#"{{"type":"deposit","client":{},"tx":{}}}"#, client, tx)
+            }
+            Malformed::InvalidAmount => format!(
+                r#"{{"type":"deposit","client":{},"tx":{},"amount":"1.0.0"}}"#,
+                client, tx
+            ),
+            Malformed::Truncated => format!(r#"{{"type":"deposit","client":{}"#, client),
+        },
+    }
+}
+
+/// Writes `rows` in `format`, which must not be `Auto`. CSV files have a `type,client,tx,amount,to`
+/// header.
+pub fn write<W, I>(mut writer: W, format: InputFormat, rows: I) -> Result<(), Error>
+where
+    W: Write,
+    I: IntoIterator<Item = Row>,
+{
+    let err = |e: std::io::Error| Error::Serialization(e.to_string());
+    let render = match format {
+        InputFormat::Json => json_row,
+        _ => {
+            writeln!(writer, "type,client,tx,amount,to").map_err(err)?;
+            csv_row
+        }
+    };
+    for row in rows {
+        writeln!(writer, "{}", render(&row)).map_err(err)?;
+    }
+    writer.flush().map_err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv_utils::CsvSource;
+    use crate::json_utils::JsonSource;
+
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let config = GeneratorConfig {
+            clients: 10,
+            count: 10_000,
+            disputes: 0.02,
+            malformed: 0.01,
+            seed: 42,
+            ..Default::default()
+        };
+        let rows: Vec<_> = Generator::new(config.clone()).collect();
+        assert_eq!(rows.len(), 10_000);
+        assert_eq!(Generator::new(config.clone()).collect::<Vec<_>>(), rows);
+        let other = GeneratorConfig {
+            seed: 43,
+            ..config.clone()
+        };
+        assert_ne!(Generator::new(other).collect::<Vec<_>>(), rows);
+
+        let count = |f: fn(&Row) -> bool| rows.iter().filter(|r| f(r)).count();
+        let malformed = count(|r| matches!(r, Row::Malformed { .. }));
+        let disputes = count(|r| matches!(r, Row::Txn(Txn::Dispute { .. })));
+        let resolves = count(|r| matches!(r, Row::Txn(Txn::Resolve { .. })));
+        assert!((50..150).contains(&malformed), "{}", malformed);
+        assert!((100..300).contains(&disputes), "{}", disputes);
+        // 70% of the disputes, minus the ones still pending at the end
+        assert!((50..disputes).contains(&resolves), "{}", resolves);
+
+        for format in [InputFormat::Csv, InputFormat::Json] {
+            let mut buf = Vec::new();
+            write(&mut buf, format, rows.clone()).expect("Cannot write rows");
+            let parsed: Vec<_> = match format {
+                InputFormat::Json => {
+                    JsonSource::from_reader("test".to_string(), buf.as_slice()).collect()
+                }
+                _ => CsvSource::from_reader("test".to_string(), buf.as_slice())
+                    .expect("Cannot read CSV")
+                    .collect(),
+            };
+            assert_eq!(parsed.len(), rows.len());
+            for (row, parsed) in rows.iter().zip(parsed) {
+                match row {
+                    Row::Txn(txn) => assert_eq!(parsed.as_ref(), Ok(txn)),
+                    Row::Malformed { .. } => assert!(parsed.is_err(), "{:?}", parsed),
+                }
+            }
+        }
+
+        let bad = GeneratorConfig {
+            withdrawals: 0.9,
+            disputes: 0.2,
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+        assert!(GeneratorConfig::default().validate().is_ok());
+    }
+}
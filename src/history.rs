@@ -0,0 +1,257 @@
+//! The transactions that changed the funds of each client, which is what disputes refer to.
+//!
+//! Every transaction is kept as a small `Record` with what it did and whether it's disputed,
+//! rather than as a whole `Txn`. Timestamps are kept apart, and only for the transactions that
+//! have one, since most inputs don't.
+
+use std::collections::{hash_map::Entry, HashMap};
+
+use crate::amount::Amount;
+use crate::types::{ClientId, Error, Timestamp, Txn, TxnId};
+
+/// The error for a transaction whose id its client already used.
+pub(crate) fn duplicate(tx: TxnId) -> Error {
+    Error::InvalidTransaction(tx, "Duplicate transaction".to_string())
+}
+
+/// What a transaction did to the funds of the client it's recorded for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Deposit,
+    Withdrawal,
+    /// A transfer from another client
+    TransferIn(ClientId),
+    /// A transfer to another client
+    TransferOut(ClientId),
+    Interest,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    Applied,
+    Disputed,
+    ChargedBack,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Record {
+    pub kind: Kind,
+    pub state: State,
+    pub amount: Amount,
+}
+
+impl Record {
+    /// Whether the transaction took funds away from the client, so that disputing it is
+    /// disputing a withdrawal.
+    pub fn outgoing(&self) -> bool {
+        matches!(self.kind, Kind::Withdrawal | Kind::TransferOut(_))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    records: HashMap<(TxnId, ClientId), Record>,
+    timestamps: HashMap<(TxnId, ClientId), Timestamp>,
+    // The amounts of the open disputes of each client
+    disputed: HashMap<ClientId, Amount>,
+}
+
+impl History {
+    /// Records `txn` for `client`, which must be one of its sides. Only deposits, withdrawals,
+    /// transfers and interest change funds, anything else is ignored. Fails if `client` already
+    /// has a transaction with the same id, which is kept as it was.
+    pub fn insert(&mut self, client: ClientId, txn: &Txn) -> Result<(), Error> {
+        let kind = match txn {
+            Txn::Deposit { .. } => Kind::Deposit,
+            Txn::Withdrawal { .. } => Kind::Withdrawal,
+            Txn::Interest { .. } => Kind::Interest,
+            Txn::Transfer {
+                client: from, to, ..
+            } if *from == client => Kind::TransferOut(*to),
+            Txn::Transfer { client: from, .. } => Kind::TransferIn(*from),
+            _ => return Ok(()),
+        };
+        let Some(amount) = txn.amount() else {
+            return Ok(());
+        };
+
+        let key = (txn.tx(), client);
+        let record = Record {
+            kind,
+            state: State::Applied,
+            amount,
+        };
+        match self.records.entry(key) {
+            Entry::Occupied(_) => return Err(duplicate(key.0)),
+            Entry::Vacant(e) => e.insert(record),
+        };
+        if let Some(t) = txn.timestamp() {
+            self.timestamps.insert(key, t);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, tx: TxnId, client: ClientId) -> Option<&Record> {
+        self.records.get(&(tx, client))
+    }
+
+    pub fn contains(&self, tx: TxnId, client: ClientId) -> bool {
+        self.records.contains_key(&(tx, client))
+    }
+
+    pub fn timestamp(&self, tx: TxnId, client: ClientId) -> Option<Timestamp> {
+        self.timestamps.get(&(tx, client)).copied()
+    }
+
+    /// Moves a recorded transaction to `state`, keeping the sums of the open disputes up to date.
+    pub fn set_state(&mut self, tx: TxnId, client: ClientId, state: State) {
+        let Some(record) = self.records.get_mut(&(tx, client)) else {
+            return;
+        };
+        let sum = self.disputed.entry(client).or_default();
+        if record.state == State::Disputed {
+            *sum = *sum - record.amount;
+        }
+        if state == State::Disputed {
+            *sum = *sum + record.amount;
+        }
+        record.state = state;
+    }
+
+    /// The sum of the amounts of the open disputes of `client`.
+    pub fn disputed(&self, client: ClientId) -> Amount {
+        self.disputed.get(&client).copied().unwrap_or_default()
+    }
+
+    /// The transaction that was recorded as `tx` for `client`, as it was processed.
+    pub fn txn(&self, tx: TxnId, client: ClientId) -> Option<Txn> {
+        let record = self.get(tx, client)?;
+        let timestamp = self.timestamp(tx, client);
+        let amount = record.amount;
+        Some(match record.kind {
+            Kind::Deposit => Txn::Deposit {
+                client,
+                tx,
+                amount,
+                timestamp,
+            },
+            Kind::Withdrawal => Txn::Withdrawal {
+                client,
+                tx,
+                amount,
+                timestamp,
+            },
+            Kind::TransferIn(from) => Txn::Transfer {
+                client: from,
+                to: client,
+                tx,
+                amount,
+                timestamp,
+            },
+            Kind::TransferOut(to) => Txn::Transfer {
+                client,
+                to,
+                tx,
+                amount,
+                timestamp,
+            },
+            Kind::Interest => Txn::Interest {
+                client,
+                tx,
+                amount,
+                timestamp,
+            },
+        })
+    }
+
+    /// All records, in no particular order, along with their transaction id and client.
+    pub fn iter(&self) -> impl Iterator<Item = (TxnId, ClientId, &Record)> {
+        self.records
+            .iter()
+            .map(|((tx, client), r)| (*tx, *client, r))
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.timestamps.clear();
+        self.disputed.clear();
+    }
+
+    /// Adds the records of `other`, which must have seen other clients.
+    pub(crate) fn merge(&mut self, other: History) {
+        self.records.extend(other.records);
+        self.timestamps.extend(other.timestamps);
+        for (client, amount) in other.disputed {
+            let sum = self.disputed.entry(client).or_default();
+            *sum = *sum + amount;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::default();
+        let transfer = Txn::Transfer {
+            client: 1,
+            to: 2,
+            tx: 7,
+            amount: 15000.into(),
+            timestamp: Some(1704164645),
+        };
+        assert_eq!(history.insert(1, &transfer), Ok(()));
+        assert_eq!(history.insert(2, &transfer), Ok(()));
+        let dispute = Txn::Dispute {
+            client: 1,
+            tx: 8,
+            timestamp: None,
+        };
+        assert_eq!(history.insert(1, &dispute), Ok(()));
+
+        assert_eq!(
+            history.get(7, 1).map(|r| r.kind),
+            Some(Kind::TransferOut(2))
+        );
+        assert_eq!(history.get(7, 2).map(|r| r.kind), Some(Kind::TransferIn(1)));
+        assert_eq!(history.txn(7, 1), Some(transfer.clone()));
+        assert_eq!(history.txn(7, 2), Some(transfer));
+        assert_eq!(history.iter().count(), 2);
+
+        history.set_state(7, 2, State::Disputed);
+        assert_eq!(history.disputed(2), 15000.into());
+        assert_eq!(history.disputed(1), 0.into());
+        history.set_state(7, 2, State::ChargedBack);
+        assert_eq!(history.get(7, 2).map(|r| r.state), Some(State::ChargedBack));
+        assert_eq!(history.disputed(2), 0.into());
+
+        // Transactions that don't change funds are not recorded
+        assert!(!history.contains(8, 1));
+        assert_eq!(std::mem::size_of::<Record>(), 16);
+    }
+
+    #[test]
+    fn test_no_overwrite() {
+        let mut history = History::default();
+        let deposit = Txn::Deposit {
+            client: 1,
+            tx: 7,
+            amount: 15000.into(),
+            timestamp: None,
+        };
+        assert_eq!(history.insert(1, &deposit), Ok(()));
+        history.set_state(7, 1, State::Disputed);
+
+        let withdrawal = Txn::Withdrawal {
+            client: 1,
+            tx: 7,
+            amount: 1.into(),
+            timestamp: None,
+        };
+        assert_eq!(history.insert(1, &withdrawal), Err(duplicate(7)));
+        assert_eq!(history.txn(7, 1), Some(deposit));
+        assert_eq!(history.disputed(1), 15000.into());
+    }
+}
@@ -0,0 +1,326 @@
+//! An HTTP API to process transactions and query accounts. All bodies are JSON:
+//!
+//! * `POST /transactions` with a transaction in the same format as a JSON Lines input row. Replies
+//!   `200 {"result":"applied"}`, or `422 {"result":"rejected","error":{"code":...,"message":...}}`
+//!   if it could not be applied (`400` if it could not even be parsed).
+//! * `GET /accounts` lists all accounts, sorted by client, as in the JSON output format.
+//! * `GET /accounts/<client>` returns a single account, or `404` if it doesn't exist.
+//! * `GET /disputes` lists the open disputes, sorted by client and transaction.
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use itertools::sorted;
+use log::warn;
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::json_utils;
+use crate::output::Output;
+use crate::processor::Processor;
+use crate::types::{ClientId, Error, Txn, TxnId};
+
+// Number of threads handling requests. They all share the same processor, so more threads would
+// only help with slow clients.
+const WORKERS: usize = 4;
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+impl From<&Error> for ErrorBody {
+    fn from(e: &Error) -> Self {
+        ErrorBody {
+            code: e.code(),
+            message: e.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TxnResult {
+    result: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorBody>,
+}
+
+#[derive(Serialize)]
+struct ErrorResult {
+    error: ErrorBody,
+}
+
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+struct Dispute {
+    client: ClientId,
+    tx: TxnId,
+    amount: String,
+}
+
+/// Handles requests on `server` until it is unblocked, with all requests sharing the same
+/// `Processor`.
+pub fn serve(server: Server, p: Arc<Mutex<Processor>>) {
+    let server = Arc::new(server);
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let server = server.clone();
+            let p = p.clone();
+            thread::spawn(move || {
+                for mut req in server.incoming_requests() {
+                    let (status, body) = handle(&mut req, &p);
+                    let header = Header::from_bytes("Content-Type", "application/json")
+                        .expect("Invalid header");
+                    let resp = Response::from_string(body)
+                        .with_status_code(status)
+                        .with_header(header);
+                    if let Err(e) = req.respond(resp) {
+                        warn!("Error sending response: {}", e);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for w in workers {
+        let _ = w.join();
+    }
+}
+
+fn handle(req: &mut Request, p: &Mutex<Processor>) -> (u16, String) {
+    let url = req.url().to_string();
+    let segments: Vec<_> = url
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    match (req.method(), segments.as_slice()) {
+        (Method::Post, ["transactions"]) => {
+            let mut body = String::new();
+            if let Err(e) = req.as_reader().read_to_string(&mut body) {
+                return error(400, &Error::Input(e.to_string()));
+            }
+            post_transaction(&body, p)
+        }
+
+        (Method::Get, ["accounts"]) => {
+            let p = p.lock().expect("Processor lock poisoned");
+            let mut accts: Vec<Output> = p.get_accounts().map(|a| Output::new(a, &p)).collect();
+            accts.sort_by_key(|o| o.client);
+            json(200, &accts)
+        }
+
+        (Method::Get, ["accounts", client]) => match client.parse::<ClientId>() {
+            Ok(client) => {
+                let p = p.lock().expect("Processor lock poisoned");
+                match p.get_account(client) {
+                    Some(acct) => json(200, &Output::new(acct, &p)),
+                    None => error(404, &Error::UnknownClient(client)),
+                }
+            }
+            Err(e) => error(
+                400,
+                &Error::Input(format!("Invalid client {}: {}", client, e)),
+            ),
+        },
+
+        (Method::Get, ["disputes"]) => {
+            let p = p.lock().expect("Processor lock poisoned");
+            let disputes: Vec<_> = sorted(p.get_disputes().filter_map(|(client, t)| match t {
+                Txn::Deposit { tx, amount, .. }
+                | Txn::Withdrawal { tx, amount, .. }
+                | Txn::Transfer { tx, amount, .. } => Some(Dispute {
+                    client,
+                    tx,
+                    amount: String::from(&amount),
+                }),
+                _ => None,
+            }))
+            .collect();
+            json(200, &disputes)
+        }
+
+        (_, ["transactions"] | ["accounts"] | ["accounts", _] | ["disputes"]) => error(
+            405,
+            &Error::Input(format!("Method not allowed: {}", req.method())),
+        ),
+
+        _ => error(404, &Error::Input(format!("Not found: {}", url))),
+    }
+}
+
+fn post_transaction(body: &str, p: &Mutex<Processor>) -> (u16, String) {
+    let txn = match json_utils::parse_txn(body.trim()) {
+        Ok(t) => t,
+        Err(e) => return error(400, &e),
+    };
+
+    match p.lock().expect("Processor lock poisoned").process_txn(&txn) {
+        Ok(()) => json(
+            200,
+            &TxnResult {
+                result: "applied",
+                error: None,
+            },
+        ),
+        Err(e) => json(
+            422,
+            &TxnResult {
+                result: "rejected",
+                error: Some((&e).into()),
+            },
+        ),
+    }
+}
+
+fn error(status: u16, e: &Error) -> (u16, String) {
+    json(status, &ErrorResult { error: e.into() })
+}
+
+fn json<T: Serialize>(status: u16, body: &T) -> (u16, String) {
+    match serde_json::to_string(body) {
+        Ok(s) => (status, s),
+        Err(e) => (
+            500,
+            format!(
+                r#"{{"error":{{"code":"serialization","message":"{}"}}}}"#,
+                e
+            ),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+    };
+
+    use super::*;
+
+    fn start() -> SocketAddr {
+        let server = Server::http("127.0.0.1:0").expect("Cannot start server");
+        let addr = server.server_addr().to_ip().expect("Not an IP address");
+        let p = Arc::new(Mutex::new(Processor::default()));
+        thread::spawn(move || serve(server, p));
+        addr
+    }
+
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).expect("Cannot connect");
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .expect("Cannot send request");
+
+        let mut resp = String::new();
+        stream
+            .read_to_string(&mut resp)
+            .expect("Cannot read response");
+        let (head, body) = resp.split_once("\r\n\r\n").expect("Invalid response");
+        let status = head
+            .split(' ')
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .expect("Invalid status line");
+        (status, body.to_string())
+    }
+
+    #[test]
+    fn test_api() {
+        let addr = start();
+
+        let deposit = r#"{"type":"deposit","client":1,"tx":1,"amount":"10"}"#;
+        assert_eq!(
+            request(addr, "POST", "/transactions", deposit),
+            (200, r#"{"result":"applied"}"#.to_string())
+        );
+
+        let withdrawal = r#"{"type":"withdrawal","client":1,"tx":2,"amount":"20"}"#;
+        assert_eq!(
+            request(addr, "POST", "/transactions", withdrawal),
+            (
+                422,
+                r#"{"result":"rejected","error":{"code":"insufficient_funds","message":"Transaction 2: Insufficient funds"}}"#.to_string()
+            )
+        );
+
+        let (status, body) = request(addr, "POST", "/transactions", "{}");
+        assert_eq!(status, 400);
+        assert!(body.contains(r#""code":"invalid_input""#));
+
+        let dispute = r#"{"type":"dispute","client":1,"tx":1}"#;
+        assert_eq!(request(addr, "POST", "/transactions", dispute).0, 200);
+        let deposit = r#"{"type":"deposit","client":2,"tx":3,"amount":"0.5"}"#;
+        assert_eq!(request(addr, "POST", "/transactions", deposit).0, 200);
+
+        assert_eq!(
+            request(addr, "GET", "/accounts/1", ""),
+            (
+                200,
+                r#"{"client":1,"available":"0.0000","held":"10.0000","total":"10.0000","locked":false}"#.to_string()
+            )
+        );
+        assert_eq!(
+            request(addr, "GET", "/accounts/3", ""),
+            (
+                404,
+                r#"{"error":{"code":"unknown_client","message":"Unknown client: 3"}}"#.to_string()
+            )
+        );
+        assert_eq!(request(addr, "GET", "/accounts/x", "").0, 400);
+        assert_eq!(
+            request(addr, "GET", "/accounts", ""),
+            (
+                200,
+                r#"[{"client":1,"available":"0.0000","held":"10.0000","total":"10.0000","locked":false},{"client":2,"available":"0.5000","held":"0.0000","total":"0.5000","locked":false}]"#.to_string()
+            )
+        );
+        assert_eq!(
+            request(addr, "GET", "/disputes", ""),
+            (
+                200,
+                r#"[{"client":1,"tx":1,"amount":"10.0000"}]"#.to_string()
+            )
+        );
+
+        assert_eq!(request(addr, "DELETE", "/accounts", "").0, 405);
+        assert_eq!(request(addr, "GET", "/nothing", "").0, 404);
+    }
+
+    #[test]
+    fn test_concurrent_requests() {
+        let addr = start();
+
+        let clients: Vec<_> = (0..8)
+            .map(|t| {
+                thread::spawn(move || {
+                    for i in 0..25 {
+                        let deposit = format!(
+                            r#"{{"type":"deposit","client":1,"tx":{},"amount":"1"}}"#,
+                            t * 100 + i
+                        );
+                        assert_eq!(request(addr, "POST", "/transactions", &deposit).0, 200);
+                    }
+                })
+            })
+            .collect();
+        for c in clients {
+            c.join().expect("Client thread panicked");
+        }
+
+        let (_, body) = request(addr, "GET", "/accounts/1", "");
+        assert!(body.contains(r#""available":"200.0000""#), "{}", body);
+    }
+}
@@ -0,0 +1,191 @@
+use std::{fmt::Display, fs::File, io::Read, path::Path, sync::Arc};
+
+use clap::ValueEnum;
+
+use crate::csv_utils::CsvSource;
+use crate::json_utils::JsonSource;
+use crate::types::{Error, Txn};
+
+// How many bytes to look at when sniffing the format of a file
+const SNIFF_LEN: u64 = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    /// Detect the format from the file extension or, failing that, from its contents
+    Auto,
+    Csv,
+    /// One JSON object per line (JSON Lines / NDJSON)
+    Json,
+}
+
+impl InputFormat {
+    /// The format of `path` going by its extension alone, if it's a known one (`.csv`, `.json`,
+    /// `.jsonl`, `.ndjson`).
+    pub fn from_extension(path: &Path) -> Option<InputFormat> {
+        let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        match ext.as_deref() {
+            Some("csv") => Some(InputFormat::Csv),
+            Some("json" | "jsonl" | "ndjson") => Some(InputFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Resolves `Auto` into a concrete format. Known extensions win (see `from_extension`);
+    /// otherwise a file whose first non-blank character is `{` is taken to be JSON Lines and
+    /// anything else CSV.
+    pub fn detect(self, path: &Path) -> Result<InputFormat, Error> {
+        if self != InputFormat::Auto {
+            return Ok(self);
+        }
+        if let Some(format) = InputFormat::from_extension(path) {
+            return Ok(format);
+        }
+
+        let err =
+            |e: std::io::Error| Error::Deserialization(path.display().to_string(), e.to_string());
+        let mut buf = Vec::new();
+        File::open(path)
+            .and_then(|f| f.take(SNIFF_LEN).read_to_end(&mut buf))
+            .map_err(err)?;
+
+        match buf.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Ok(InputFormat::Json),
+            _ => Ok(InputFormat::Csv),
+        }
+    }
+}
+
+/// Where a transaction was read from. For files `line` is the line number (starting at 1), for
+/// other sources it's the index of the item (also starting at 1).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    pub source: Arc<str>,
+    pub line: u64,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.source, self.line)
+    }
+}
+
+/// A source of transactions. It yields each transaction in order, or the error found while
+/// reading or validating it.
+pub trait TxnSource: Iterator<Item = Result<Txn, Error>> {
+    /// The position of the last item returned by `next`.
+    fn last_position(&self) -> Position;
+}
+
+/// A source for transactions that are already in memory.
+pub struct MemorySource {
+    name: Arc<str>,
+    txns: std::vec::IntoIter<Txn>,
+    idx: u64,
+}
+
+impl MemorySource {
+    pub fn new(txns: Vec<Txn>) -> Self {
+        MemorySource {
+            name: "memory".into(),
+            txns: txns.into_iter(),
+            idx: 0,
+        }
+    }
+}
+
+impl Iterator for MemorySource {
+    type Item = Result<Txn, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let txn = self.txns.next()?;
+        self.idx += 1;
+        Some(Ok(txn))
+    }
+}
+
+impl TxnSource for MemorySource {
+    fn last_position(&self) -> Position {
+        Position {
+            source: self.name.clone(),
+            line: self.idx,
+        }
+    }
+}
+
+/// Opens the file at `path` as a transaction source.
+pub fn open(path: &Path, format: InputFormat) -> Result<Box<dyn TxnSource>, Error> {
+    match format.detect(path)? {
+        InputFormat::Json => Ok(Box::new(JsonSource::open(path)?)),
+        _ => Ok(Box::new(CsvSource::open(path)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn detect(suffix: &str, contents: &str) -> InputFormat {
+        let mut file = tempfile::Builder::new()
+            .suffix(suffix)
+            .tempfile()
+            .expect("Cannot create temporary file");
+        file.write_all(contents.as_bytes())
+            .expect("Cannot write temporary file");
+        InputFormat::Auto
+            .detect(file.path())
+            .expect("Cannot detect format")
+    }
+
+    #[test]
+    fn test_detect_by_extension() {
+        assert_eq!(detect(".csv", "{}"), InputFormat::Csv);
+        assert_eq!(detect(".json", "type,client"), InputFormat::Json);
+        assert_eq!(detect(".JSONL", "type,client"), InputFormat::Json);
+        assert_eq!(detect(".ndjson", "type,client"), InputFormat::Json);
+    }
+
+    #[test]
+    fn test_detect_by_content() {
+        assert_eq!(detect(".txt", "type,client,tx,amount\n"), InputFormat::Csv);
+        assert_eq!(
+            detect("", "\n  {\"type\":\"deposit\"}\n"),
+            InputFormat::Json
+        );
+        assert_eq!(detect("", ""), InputFormat::Csv);
+    }
+
+    #[test]
+    fn test_memory_source() {
+        let txns = vec![
+            Txn::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 10000.into(),
+                timestamp: None,
+            },
+            Txn::Dispute {
+                client: 1,
+                tx: 1,
+                timestamp: None,
+            },
+        ];
+        let mut src = MemorySource::new(txns.clone());
+
+        assert_eq!(src.next(), Some(Ok(txns[0].clone())));
+        assert_eq!(src.last_position().to_string(), "memory:1");
+        assert_eq!(src.next(), Some(Ok(txns[1].clone())));
+        assert_eq!(src.last_position().to_string(), "memory:2");
+        assert_eq!(src.next(), None);
+    }
+
+    #[test]
+    fn test_explicit_format_is_kept() {
+        let path = Path::new("does/not/exist.json");
+        assert_eq!(InputFormat::Csv.detect(path), Ok(InputFormat::Csv));
+        assert!(InputFormat::Auto
+            .detect(Path::new("does/not/exist"))
+            .is_err());
+    }
+}
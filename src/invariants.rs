@@ -0,0 +1,231 @@
+//! Accounting invariants, checked after every transaction by `Processor::set_check_invariants`:
+//!
+//! * The total of every account, as it is reported, is its available plus its held funds.
+//! * The held funds of every account are the sum of the amounts of its open disputes.
+//! * The money that came in (deposits and interest) minus the money that went out (withdrawals
+//!   and chargebacks) is the sum of the totals of all accounts.
+//!
+//! Only the accounts a transaction touches are checked, and the sum of the totals is kept up to
+//! date with their changes, so that the checks don't slow down with the number of accounts. The
+//! total of an account is not stored but derived from its available and held funds, so it only
+//! disagrees with them if their sum is out of range. Violations are logged as errors as soon as
+//! they are found.
+
+use std::fmt::Display;
+
+use log::error;
+
+use crate::amount::Amount;
+use crate::output::Output;
+use crate::processor::AccountChange;
+use crate::types::{Account, ClientId, Txn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Invariant {
+    Total,
+    Held,
+    Money,
+}
+
+impl Display for Invariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Invariant::Total => write!(f, "total = available + held"),
+            Invariant::Held => write!(f, "held = open disputes"),
+            Invariant::Money => write!(f, "money in - money out = sum of totals"),
+        }
+    }
+}
+
+/// An invariant that didn't hold right after `txn` was processed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub txn: Txn,
+    pub invariant: Invariant,
+    pub detail: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Transaction {} (`{}`) breaks `{}`: {}",
+            self.txn.tx(),
+            self.txn,
+            self.invariant,
+            self.detail
+        )
+    }
+}
+
+/// The running sums the invariants are checked against, and the violations found so far.
+#[derive(Clone, Debug, Default)]
+pub struct Invariants {
+    // Money in minus money out
+    money: Amount,
+    totals: Amount,
+    violations: Vec<Violation>,
+}
+
+fn total(acct: &Option<Account>) -> Amount {
+    match acct {
+        Some(Account::Locked(a) | Account::Unlocked(a)) => a.available + a.held,
+        None => Amount::default(),
+    }
+}
+
+impl Invariants {
+    /// Checks the invariants after `txn`, which moved `flow` into (or out of, if negative) the
+    /// ledger and made `changes`. `disputed` gives the sum of the open disputes of a client.
+    pub(crate) fn check<F>(
+        &mut self,
+        txn: &Txn,
+        flow: Amount,
+        changes: &[AccountChange],
+        disputed: F,
+    ) where
+        F: Fn(ClientId) -> Amount,
+    {
+        let mut found = Vec::new();
+        let mut violation = |invariant, detail| found.push((invariant, detail));
+
+        for change in changes {
+            let Some(acct @ (Account::Locked(a) | Account::Unlocked(a))) = &change.after else {
+                continue;
+            };
+
+            let reported = Output::from(acct).total;
+            let total = a.available.checked_add(a.held);
+            if total.map(|t| String::from(&t)).as_ref() != Some(&reported) {
+                violation(
+                    Invariant::Total,
+                    format!(
+                        "client {} has a total of {}, but {} + {} is out of range",
+                        a.client, reported, a.available, a.held
+                    ),
+                );
+            }
+
+            let held = disputed(a.client);
+            if a.held != held {
+                violation(
+                    Invariant::Held,
+                    format!(
+                        "client {} holds {}, but its open disputes are for {}",
+                        a.client, a.held, held
+                    ),
+                );
+            }
+        }
+
+        self.money = self.money + flow;
+        for change in changes {
+            self.totals = self.totals + total(&change.after) - total(&change.before);
+        }
+        if self.money != self.totals {
+            violation(
+                Invariant::Money,
+                format!(
+                    "{} came in, but the accounts have {}",
+                    self.money, self.totals
+                ),
+            );
+            // Report each discrepancy once
+            self.money = self.totals;
+        }
+
+        for (invariant, detail) in found {
+            let v = Violation {
+                txn: txn.clone(),
+                invariant,
+                detail,
+            };
+            error!("{}", v);
+            self.violations.push(v);
+        }
+    }
+
+    /// All violations found so far, in the order they were found.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Adds the sums and violations of `other`, which must have seen other clients.
+    pub(crate) fn merge(&mut self, other: Invariants) {
+        self.money = self.money + other.money;
+        self.totals = self.totals + other.totals;
+        self.violations.extend(other.violations);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::AccountData;
+
+    use super::*;
+
+    #[test]
+    fn test_money() {
+        let mut invariants = Invariants::default();
+        let deposit = Txn::Deposit {
+            client: 1,
+            tx: 1,
+            amount: 10.into(),
+            timestamp: None,
+        };
+        let change = |available: i64| AccountChange {
+            client: 1,
+            before: None,
+            after: Some(Account::Unlocked(AccountData {
+                client: 1,
+                available: available.into(),
+                held: 0.into(),
+            })),
+        };
+
+        invariants.check(&deposit, 10.into(), &[change(10)], |_| 0.into());
+        assert_eq!(invariants.violations(), &[]);
+
+        // Creates the account again, with more than was deposited
+        invariants.check(&deposit, 0.into(), &[change(11)], |_| 0.into());
+        let violations: Vec<_> = invariants
+            .violations()
+            .iter()
+            .map(|v| v.invariant)
+            .collect();
+        assert_eq!(violations, vec![Invariant::Money]);
+
+        // Only reported once
+        invariants.check(&deposit, 0.into(), &[], |_| 0.into());
+        assert_eq!(invariants.violations().len(), 1);
+    }
+
+    #[test]
+    fn test_total() {
+        let mut invariants = Invariants::default();
+        let deposit = Txn::Deposit {
+            client: 1,
+            tx: 1,
+            amount: i64::MAX.into(),
+            timestamp: None,
+        };
+        let change = AccountChange {
+            client: 1,
+            before: None,
+            after: Some(Account::Locked(AccountData {
+                client: 1,
+                available: i64::MAX.into(),
+                held: 1.into(),
+            })),
+        };
+
+        // The total is reported as the largest amount, one less than it should be
+        invariants.check(&deposit, i64::MAX.into(), &[change], |_| 1.into());
+        let violations: Vec<_> = invariants
+            .violations()
+            .iter()
+            .map(|v| v.invariant)
+            .collect();
+        assert_eq!(violations, vec![Invariant::Total]);
+    }
+}
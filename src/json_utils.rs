@@ -0,0 +1,295 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::csv_utils::Input;
+use crate::input::{Position, TxnSource};
+use crate::output::{AccountWriter, Output};
+use crate::types::{Error, Txn};
+
+/// Writes all accounts as a single JSON array.
+pub struct JsonWriter<W: Write> {
+    writer: W,
+    first: bool,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        JsonWriter {
+            writer,
+            first: true,
+        }
+    }
+}
+
+impl<W: Write> AccountWriter for JsonWriter<W> {
+    fn write(&mut self, out: &Output) -> Result<(), Error> {
+        let sep: &[u8] = if self.first { b"[" } else { b"," };
+        self.first = false;
+        self.writer
+            .write_all(sep)
+            .map_err(|e| Error::Serialization(e.to_string()))?;
+        serde_json::to_writer(&mut self.writer, out)
+            .map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        let end: &[u8] = if self.first { b"[]\n" } else { b"]\n" };
+        self.writer
+            .write_all(end)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| Error::Serialization(e.to_string()))
+    }
+}
+
+/// Writes one JSON object per account, each on its own line.
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        NdjsonWriter { writer }
+    }
+}
+
+impl<W: Write> AccountWriter for NdjsonWriter<W> {
+    fn write(&mut self, out: &Output) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.writer, out)
+            .map_err(|e| Error::Serialization(e.to_string()))?;
+        self.writer
+            .write_all(b"\n")
+            .map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.writer
+            .flush()
+            .map_err(|e| Error::Serialization(e.to_string()))
+    }
+}
+
+/// Renders a transaction as a single line of JSON, the reverse of `parse_txn`. Amounts are
+/// strings, and `to` and `timestamp` are only there if the transaction has them.
+pub fn to_json(txn: &Txn) -> String {
+    let tpe = match txn {
+        Txn::Deposit { .. } => "deposit",
+        Txn::Withdrawal { .. } => "withdrawal",
+        Txn::Dispute { .. } => "dispute",
+        Txn::Resolve { .. } => "resolve",
+        Txn::Chargeback { .. } => "chargeback",
+        Txn::AccrueInterest { .. } => "accrue_interest",
+        Txn::Interest { .. } => "interest",
+        Txn::Transfer { .. } => "transfer",
+    };
+    let mut obj = serde_json::json!({ "type": tpe, "tx": txn.tx() });
+    if !matches!(txn, Txn::AccrueInterest { .. }) {
+        obj["client"] = txn.client().into();
+    }
+    if let Some(amount) = txn.amount() {
+        obj["amount"] = amount.to_string().into();
+    }
+    if let Some(to) = txn.counterparty() {
+        obj["to"] = to.into();
+    }
+    if let Some(t) = txn.timestamp() {
+        obj["timestamp"] = t.into();
+    }
+    obj.to_string()
+}
+
+/// Parses a single transaction, e.g. `{"type":"deposit","client":1,"tx":2,"amount":"3.0"}`.
+pub fn parse_txn(line: &str) -> Result<Txn, Error> {
+    serde_json::from_str::<Input>(line)
+        .map_err(|e| Error::Input(e.to_string()))
+        .and_then(Txn::try_from)
+}
+
+/// Reads transactions from a JSON Lines file, where each line is an object with the same fields
+/// as a CSV row, e.g. `{"type":"deposit","client":1,"tx":1,"amount":"1.5"}`. Blank lines are
+/// ignored.
+pub struct JsonSource<R: BufRead> {
+    name: Arc<str>,
+    reader: R,
+    buf: Vec<u8>,
+    line: u64,
+    done: bool,
+}
+
+impl JsonSource<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let name = path.display().to_string();
+        let file =
+            File::open(path).map_err(|e| Error::Deserialization(name.clone(), e.to_string()))?;
+        Ok(JsonSource::from_reader(name, BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> JsonSource<R> {
+    /// Creates a source reading from `reader`. `name` is only used to report errors and positions.
+    pub fn from_reader(name: String, reader: R) -> Self {
+        JsonSource {
+            name: name.into(),
+            reader,
+            buf: Vec::new(),
+            line: 0,
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JsonSource<R> {
+    type Item = Result<Txn, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            self.buf.clear();
+            match self.reader.read_until(b'\n', &mut self.buf) {
+                Ok(0) => self.done = true,
+                Ok(_) => {
+                    self.line += 1;
+                    if self.buf.trim_ascii().is_empty() {
+                        continue;
+                    }
+                    return Some(
+                        serde_json::from_slice::<Input>(&self.buf)
+                            .map_err(|e| {
+                                Error::Deserialization(self.name.to_string(), e.to_string())
+                            })
+                            .and_then(Txn::try_from),
+                    );
+                }
+                Err(e) => {
+                    // Don't risk hitting the same I/O error forever
+                    self.done = true;
+                    return Some(Err(Error::Deserialization(
+                        self.name.to_string(),
+                        e.to_string(),
+                    )));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<R: BufRead> TxnSource for JsonSource<R> {
+    fn last_position(&self) -> Position {
+        Position {
+            source: self.name.clone(),
+            line: self.line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_txn() {
+        assert_eq!(
+            parse_txn(r#"{"type":"withdrawal","client":1,"tx":2,"amount":"3.5"}"#),
+            Ok(Txn::Withdrawal {
+                client: 1,
+                tx: 2,
+                amount: 35000.into(),
+                timestamp: None,
+            })
+        );
+        assert_eq!(
+            parse_txn(r#"{"type":"dispute","client":1,"tx":2}"#),
+            Ok(Txn::Dispute {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            })
+        );
+        for t in ["1704164645", "\"1704164645\"", "\"2024-01-02T03:04:05Z\""] {
+            assert_eq!(
+                parse_txn(&format!(
+                    r#"{{"type":"dispute","client":1,"tx":2,"timestamp":{}}}"#,
+                    t
+                )),
+                Ok(Txn::Dispute {
+                    client: 1,
+                    tx: 2,
+                    timestamp: Some(1704164645),
+                })
+            );
+        }
+        assert!(matches!(
+            parse_txn(r#"{"type":"dispute","client":1}"#),
+            Err(Error::Input(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_source() {
+        let json_str = r#"{"type":"deposit","client":1,"tx":2,"amount":"3"}
+{"type":"withdrawal","client":1,"tx":2,"amount":"3.5"}
+
+{"type":"dispute","client":1,"tx":2}
+{"type":"resolve","client":1,"tx":2,"amount":null}
+{"type":"chargeback","client":1,"tx":2}
+{"type":"deposit","client":1,"tx":3}
+{"type":"deposit","client":1,"tx":4,"amount":3}
+not json
+"#;
+        let mut src = JsonSource::from_reader("test.json".to_string(), json_str.as_bytes());
+
+        let mut actual = Vec::new();
+        let mut errs = Vec::new();
+        while let Some(item) = src.next() {
+            match item {
+                Ok(txn) => actual.push(txn),
+                Err(e) => errs.push((src.last_position().to_string(), e)),
+            }
+        }
+
+        let expected = vec![
+            Txn::Deposit {
+                client: 1,
+                tx: 2,
+                amount: 30000.into(),
+                timestamp: None,
+            },
+            Txn::Withdrawal {
+                client: 1,
+                tx: 2,
+                amount: 35000.into(),
+                timestamp: None,
+            },
+            Txn::Dispute {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            },
+            Txn::Resolve {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            },
+            Txn::Chargeback {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            },
+        ];
+        assert_eq!(actual, expected);
+
+        assert_eq!(errs.len(), 3);
+        assert_eq!(
+            errs[0],
+            (
+                "test.json:7".to_string(),
+                Error::Input("Missing amount in transaction 3".to_string())
+            )
+        );
+        assert!(matches!(&errs[1], (pos, Error::Deserialization(..)) if pos == "test.json:8"));
+        assert!(matches!(&errs[2], (pos, Error::Deserialization(..)) if pos == "test.json:9"));
+    }
+}
@@ -0,0 +1,24 @@
+pub mod amount;
+pub mod clients;
+pub mod config;
+pub mod csv_utils;
+pub mod fees;
+pub mod follow;
+pub mod fraud;
+pub mod generator;
+pub mod history;
+pub mod http;
+pub mod input;
+pub mod invariants;
+pub mod json_utils;
+pub mod limits;
+#[cfg(test)]
+mod model;
+pub mod output;
+pub mod parallel;
+pub mod processor;
+pub mod reconcile;
+pub mod repl;
+pub mod server;
+pub mod summary;
+pub mod types;
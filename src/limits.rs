@@ -0,0 +1,346 @@
+//! Per-client limits on withdrawals and on the number of transactions, configured in a TOML file
+//! like:
+//!
+//! ```toml
+//! [default]
+//! max_withdrawal = "1000"
+//! max_daily_withdrawal = "5000"
+//! max_txns = 10
+//! window_secs = 60
+//!
+//! [[client]]
+//! client = 1
+//! max_withdrawal = "20000"
+//! ```
+//!
+//! Every setting of a `[[client]]` entry overrides the default for that client, the ones it
+//! doesn't have are taken from `[default]`. Transfers count as withdrawals for the sender.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::amount::Amount;
+use crate::types::{ClientId, Error, Txn};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+    /// Largest amount for a single withdrawal
+    pub max_withdrawal: Option<Amount>,
+    /// Largest total withdrawn in a (UTC) day
+    pub max_daily_withdrawal: Option<Amount>,
+    /// Most deposits and withdrawals in any `window_secs` seconds
+    pub max_txns: Option<usize>,
+    pub window_secs: Option<u64>,
+}
+
+impl Limit {
+    // The settings of `self`, falling back to `default`
+    fn or(&self, default: &Limit) -> Limit {
+        Limit {
+            max_withdrawal: self.max_withdrawal.or(default.max_withdrawal),
+            max_daily_withdrawal: self.max_daily_withdrawal.or(default.max_daily_withdrawal),
+            max_txns: self.max_txns.or(default.max_txns),
+            window_secs: self.window_secs.or(default.window_secs),
+        }
+    }
+}
+
+// `deny_unknown_fields` doesn't work with `#[serde(flatten)]`, so all fields are repeated here
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientLimit {
+    client: ClientId,
+    max_withdrawal: Option<Amount>,
+    max_daily_withdrawal: Option<Amount>,
+    max_txns: Option<usize>,
+    window_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    default: Limit,
+    #[serde(default)]
+    client: Vec<ClientLimit>,
+}
+
+// What each client has done recently
+#[derive(Clone, Debug, Default)]
+struct Usage {
+    day: u64,
+    withdrawn: Amount,
+    // Times of the transactions still within the window
+    recent: VecDeque<u64>,
+}
+
+impl TryFrom<Config> for Limits {
+    type Error = String;
+
+    fn try_from(config: Config) -> Result<Self, Self::Error> {
+        let clients: HashMap<_, _> = config
+            .client
+            .into_iter()
+            .map(|c| {
+                let limit = Limit {
+                    max_withdrawal: c.max_withdrawal,
+                    max_daily_withdrawal: c.max_daily_withdrawal,
+                    max_txns: c.max_txns,
+                    window_secs: c.window_secs,
+                };
+                (c.client, limit)
+            })
+            .collect();
+        let limits = Limits::new(config.default, clients);
+        for l in limits.clients.values().chain([&limits.default]) {
+            if l.max_txns.is_some() && l.window_secs.is_none() {
+                return Err("max_txns needs a window_secs".to_string());
+            }
+            if l.window_secs == Some(0) {
+                return Err("window_secs must be at least 1".to_string());
+            }
+            let amounts = [l.max_withdrawal, l.max_daily_withdrawal];
+            if amounts.into_iter().flatten().any(|a| a < 0.into()) {
+                return Err("withdrawal limits can't be negative".to_string());
+            }
+        }
+        Ok(limits)
+    }
+}
+
+/// The limits of all clients, and what they have used of them so far.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "Config")]
+pub struct Limits {
+    default: Limit,
+    clients: HashMap<ClientId, Limit>,
+    usage: HashMap<ClientId, Usage>,
+}
+
+impl Limits {
+    pub fn new(default: Limit, clients: HashMap<ClientId, Limit>) -> Limits {
+        let clients = clients
+            .into_iter()
+            .map(|(c, l)| (c, l.or(&default)))
+            .collect();
+        Limits {
+            default,
+            clients,
+            usage: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let err = |e: String| Error::Deserialization(path.display().to_string(), e);
+        let s = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+        toml::from_str(&s).map_err(|e| err(e.to_string()))
+    }
+
+    fn limit(&self, client: ClientId) -> &Limit {
+        self.clients.get(&client).unwrap_or(&self.default)
+    }
+
+    /// Checks whether applying `txn` at time `now` (in seconds since the epoch) would exceed any
+    /// of the limits of its client. Without a time only `max_withdrawal` is checked.
+    pub fn check(&self, txn: &Txn, now: Option<u64>) -> Result<(), Error> {
+        let (tx, withdrawn) = match txn {
+            Txn::Deposit { tx, .. } => (*tx, None),
+            Txn::Withdrawal { tx, amount, .. } | Txn::Transfer { tx, amount, .. } => {
+                (*tx, Some(*amount))
+            }
+            _ => return Ok(()),
+        };
+        let limit = self.limit(txn.client());
+        let usage = self.usage.get(&txn.client());
+
+        if let (Some(max), Some(amount)) = (limit.max_withdrawal, withdrawn) {
+            if amount > max {
+                return Err(Error::WithdrawalLimit(tx));
+            }
+        }
+
+        let Some(now) = now else {
+            return Ok(());
+        };
+
+        if let (Some(max), Some(amount)) = (limit.max_daily_withdrawal, withdrawn) {
+            let today = usage
+                .filter(|u| u.day == now / SECS_PER_DAY)
+                .map_or(Amount::default(), |u| u.withdrawn);
+            if today + amount > max {
+                return Err(Error::DailyLimit(tx));
+            }
+        }
+
+        if let (Some(max), Some(window)) = (limit.max_txns, limit.window_secs) {
+            let recent = usage.map_or(0, |u| {
+                u.recent.iter().filter(|t| **t + window > now).count()
+            });
+            if recent >= max {
+                return Err(Error::VelocityLimit(tx));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts `txn`, which was applied at time `now`, towards the limits of its client. Without
+    /// a time it doesn't count towards any.
+    pub fn record(&mut self, txn: &Txn, now: Option<u64>) {
+        let withdrawn = match txn {
+            Txn::Deposit { .. } => Amount::default(),
+            Txn::Withdrawal { amount, .. } | Txn::Transfer { amount, .. } => *amount,
+            _ => return,
+        };
+        let Some(now) = now else {
+            return;
+        };
+        let window = self.limit(txn.client()).window_secs;
+        let usage = self.usage.entry(txn.client()).or_default();
+
+        if usage.day != now / SECS_PER_DAY {
+            usage.day = now / SECS_PER_DAY;
+            usage.withdrawn = Amount::default();
+        }
+        usage.withdrawn = usage.withdrawn + withdrawn;
+
+        if let Some(window) = window {
+            usage.recent.push_back(now);
+            while usage.recent.front().is_some_and(|t| *t + window <= now) {
+                usage.recent.pop_front();
+            }
+        }
+    }
+
+    /// Forgets all usage, but keeps the limits.
+    pub fn clear(&mut self) {
+        self.usage.clear();
+    }
+
+    /// Adds the usage of `other`, which must have seen other clients.
+    pub(crate) fn merge(&mut self, other: Limits) {
+        self.usage.extend(other.usage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn withdrawal(tx: u32, amount: i64) -> Txn {
+        Txn::Withdrawal {
+            client: 1,
+            tx,
+            amount: amount.into(),
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("limits.toml");
+        fs::write(
+            &path,
+            r#"
+[default]
+max_withdrawal = "10"
+max_txns = 2
+window_secs = 60
+
+[[client]]
+client = 1
+max_withdrawal = "20"
+"#,
+        )
+        .expect("Cannot write file");
+
+        let limits = Limits::load(&path).expect("Cannot load limits");
+        let expected = Limit {
+            max_withdrawal: Some(200000.into()),
+            max_daily_withdrawal: None,
+            max_txns: Some(2),
+            window_secs: Some(60),
+        };
+        assert_eq!(limits.limit(1), &expected);
+        assert_eq!(limits.limit(2).max_withdrawal, Some(100000.into()));
+
+        for bad in [
+            "[default]\nmax_txns = 2\n",
+            "[[client]]\nclient = 1\nmax_withdrawl = \"1\"\n",
+            "[default]\nmax_txns = 2\nwindow_secs = 0\n",
+            "[[client]]\nclient = 1\nmax_withdrawal = \"-1\"\n",
+        ] {
+            fs::write(&path, bad).expect("Cannot write file");
+            assert!(matches!(
+                Limits::load(&path),
+                Err(Error::Deserialization(..))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut limits = Limits::new(
+            Limit {
+                max_withdrawal: Some(10.into()),
+                max_daily_withdrawal: Some(25.into()),
+                max_txns: Some(3),
+                window_secs: Some(60),
+            },
+            HashMap::new(),
+        );
+        let day = SECS_PER_DAY * 100;
+
+        assert_eq!(
+            limits.check(&withdrawal(1, 11), Some(day)),
+            Err(Error::WithdrawalLimit(1))
+        );
+
+        for (tx, t) in [(2, day), (3, day + 10)] {
+            assert_eq!(limits.check(&withdrawal(tx, 10), Some(t)), Ok(()));
+            limits.record(&withdrawal(tx, 10), Some(t));
+        }
+        assert_eq!(
+            limits.check(&withdrawal(4, 6), Some(day + 20)),
+            Err(Error::DailyLimit(4))
+        );
+
+        let deposit = Txn::Deposit {
+            client: 1,
+            tx: 5,
+            amount: 100.into(),
+            timestamp: None,
+        };
+        assert_eq!(limits.check(&deposit, Some(day + 20)), Ok(()));
+        limits.record(&deposit, Some(day + 20));
+        assert_eq!(
+            limits.check(&deposit, Some(day + 59)),
+            Err(Error::VelocityLimit(5))
+        );
+        // The first one is out of the window now
+        assert_eq!(limits.check(&deposit, Some(day + 60)), Ok(()));
+
+        // A new day
+        assert_eq!(
+            limits.check(&withdrawal(6, 10), Some(day + SECS_PER_DAY)),
+            Ok(())
+        );
+
+        // Without a time only the largest withdrawal is checked
+        assert_eq!(
+            limits.check(&withdrawal(7, 11), None),
+            Err(Error::WithdrawalLimit(7))
+        );
+        assert_eq!(limits.check(&withdrawal(8, 10), None), Ok(()));
+        assert_eq!(limits.check(&deposit, None), Ok(()));
+    }
+}
@@ -1,34 +1,383 @@
-use std::io::stdout;
+use std::{
+    io::{stdin, stdout},
+    net::TcpListener,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::csv_utils::process_csv;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+use clap::Parser;
 use itertools::sorted;
 use log::{error, warn};
+use txn_processor::amount::{Amount, Rounding};
+use txn_processor::clients;
+use txn_processor::config::ProcessorConfig;
+use txn_processor::fees::FeeSchedule;
+use txn_processor::follow::Follower;
+use txn_processor::fraud::{self, FraudRules};
+use txn_processor::http;
+use txn_processor::input::{self, InputFormat};
+use txn_processor::limits::Limits;
+use txn_processor::output::{self, Format, Output};
+use txn_processor::parallel;
+use txn_processor::processor::Processor;
+use txn_processor::reconcile;
+use txn_processor::repl::Repl;
+use txn_processor::server;
+use txn_processor::types::Txn;
+
+#[derive(Parser, Debug)]
+#[command(about = "Processes a file of transactions and outputs the final account balances")]
+struct Args {
+    /// CSV or JSON Lines file with the transactions to process
+    #[arg(required_unless_present_any = ["listen", "http", "interactive"])]
+    input: Option<PathBuf>,
+
+    /// Format of the transactions file
+    #[arg(short, long, value_enum, default_value_t = InputFormat::Auto)]
+    input_format: InputFormat,
+
+    /// Write the balances to this file instead of stdout. The file is only replaced once all
+    /// balances have been written successfully.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Format of the balances output
+    #[arg(short, long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+
+    /// Number of worker threads. Transactions are partitioned among them by client.
+    #[arg(short, long, default_value_t = 1)]
+    threads: usize,
+
+    /// Instead of writing the balances, keep running and serve the line protocol on this
+    /// address: either `<host>:<port>` for TCP or `unix:<path>` for a Unix socket. If an input
+    /// file is given, it is processed before accepting connections.
+    #[arg(short, long)]
+    listen: Option<String>,
+
+    /// Instead of writing the balances, keep running and serve the HTTP API on this address
+    /// (`<host>:<port>`). Can be combined with `--listen`.
+    #[arg(long)]
+    http: Option<String>,
+
+    /// Start an interactive session to enter transactions and inspect accounts, after processing
+    /// the input file if one is given.
+    #[arg(short = 'I', long, conflicts_with_all = ["listen", "http"])]
+    interactive: bool,
+
+    /// Keep reading the input file as it grows, like `tail -f`, and write the balances every
+    /// `--interval` seconds. Rotated and truncated files are picked up.
+    #[arg(short = 'F', long, requires = "input", conflicts_with_all = ["listen", "http", "interactive", "threads"])]
+    follow: bool,
+
+    /// How often to write the balances in follow mode, in seconds
+    #[arg(long, default_value_t = 10, requires = "follow")]
+    interval: u64,
+
+    /// TOML file with the processing policies. The other options that set a policy override it.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// TOML file with the fees to charge. The balances then include the fees paid by each client.
+    #[arg(long)]
+    fees: Option<PathBuf>,
+
+    /// Percentage of the available funds credited as interest by `accrue_interest` rows, e.g.
+    /// `0.25`
+    #[arg(long, value_parser = parse_amount)]
+    interest_rate: Option<Amount>,
+
+    /// How to round interest
+    #[arg(long, value_enum, default_value_t = Rounding::HalfEven, requires = "interest_rate")]
+    interest_rounding: Rounding,
+
+    /// Accrue interest once all transactions have been processed
+    #[arg(long)]
+    accrue_interest: bool,
 
-mod amount;
-mod csv_utils;
-mod processor;
-mod types;
+    /// CSV file with the configuration of each client: `client,overdraft_limit`. The balances
+    /// then include the limit and the remaining credit of each client.
+    #[arg(long)]
+    clients: Option<PathBuf>,
+
+    /// TOML file with the limits on the withdrawals and on the number of transactions of each
+    /// client
+    #[arg(long)]
+    limits: Option<PathBuf>,
+
+    /// Reject transactions with a timestamp earlier than the latest one of the same client
+    #[arg(long)]
+    ordered_timestamps: bool,
+
+    /// Give transactions without a timestamp the time at which they are processed, for the
+    /// daily and velocity limits and the fraud rules. Otherwise the ones that depend on time
+    /// don't apply to them.
+    #[arg(long)]
+    wall_clock: bool,
+
+    /// Reject disputes more than this many days after the transaction they dispute. Only
+    /// enforced when both have a timestamp.
+    #[arg(long)]
+    dispute_window_days: Option<u64>,
+
+    /// Write a summary of the transactions processed and rejected to stderr at the end
+    #[arg(long, conflicts_with_all = ["follow", "interactive"])]
+    summary: bool,
+
+    /// TOML file with the rules that flag suspicious activity
+    #[arg(long)]
+    fraud_rules: Option<PathBuf>,
+
+    /// Write the activity flagged by the fraud rules to this CSV file at the end
+    #[arg(long, conflicts_with_all = ["follow", "interactive"])]
+    flagged: Option<PathBuf>,
+
+    /// Instead of writing the balances, compare them with the expected balances in this CSV
+    /// file, in the same format as the output. Missing clients, extra clients and different
+    /// values are written to stdout, and the exit status is 1 if there are any.
+    #[arg(long, conflicts_with_all = ["follow", "interactive", "listen", "http"])]
+    reconcile: Option<PathBuf>,
+
+    /// Check the accounting invariants after every transaction. Violations are reported as
+    /// errors, and the exit status is then 1.
+    #[arg(long)]
+    check_invariants: bool,
+}
+
+fn parse_amount(s: &str) -> Result<Amount, String> {
+    Amount::try_from(s.to_string()).map_err(|e| e.to_string())
+}
+
+// How often to check the input file for new transactions in follow mode
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn exit_on_error<T>(result: Result<T, impl std::fmt::Display>) -> T {
+    result.unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+fn write_balances(args: &Args, p: &Processor) -> Result<(), txn_processor::types::Error> {
+    let accts = sorted(p.get_accounts()).map(|a| Output::new(a, p));
+    match &args.output {
+        Some(path) => output::write_atomic(path, |w| output::save(args.format, w, accts)),
+        None => output::save(args.format, stdout(), accts),
+    }
+}
+
+fn follow(args: &Args, path: &std::path::Path, mut p: Processor) -> ! {
+    let format = match args.input_format.detect(path) {
+        Ok(format) => format,
+        // It may not have been created yet
+        Err(_) if !path.exists() => InputFormat::Csv,
+        Err(e) => exit_on_error(Err(e)),
+    };
+    let mut f = Follower::new(path, format);
+    let mut changed = true;
+    let mut last_write: Option<Instant> = None;
+
+    loop {
+        match f.poll(&mut p, |pos, e| warn!("{}: {}", pos, e)) {
+            Ok(n) => changed |= n > 0,
+            Err(e) => warn!("{}", e),
+        }
+        let due = last_write.map_or(true, |t| t.elapsed() >= Duration::from_secs(args.interval));
+        if changed && due {
+            if let Err(e) = write_balances(args, &p) {
+                error!("Error while writing output: {}", e);
+            }
+            changed = false;
+            last_write = Some(Instant::now());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Writes the differences between the accounts and the expected balances in `path` to stdout,
+// and returns whether there were none. The balances are only written with `-o`.
+fn reconcile(args: &Args, path: &std::path::Path, p: &Processor) -> bool {
+    let expected = exit_on_error(reconcile::load_expected(path));
+    if args.output.is_some() {
+        exit_on_error(write_balances(args, p));
+    }
+
+    let diffs = reconcile::reconcile(&expected, p.get_accounts().map(|a| Output::new(a, p)));
+    for d in &diffs {
+        println!("{}", d);
+    }
+    if !diffs.is_empty() {
+        error!("{} differences with {}", diffs.len(), path.display());
+    }
+    diffs.is_empty()
+}
+
+fn serve(listen: Option<&str>, http: Option<&str>, p: Processor) {
+    let p = Arc::new(Mutex::new(p));
+    let mut servers = Vec::new();
+
+    // Bind everything before starting, so that errors are reported right away
+    match listen {
+        #[cfg(unix)]
+        Some(addr) if addr.starts_with("unix:") => {
+            let listener = exit_on_error(UnixListener::bind(&addr["unix:".len()..]));
+            let p = p.clone();
+            servers.push(thread::spawn(move || server::serve_unix(listener, p)));
+        }
+        Some(addr) => {
+            let listener = exit_on_error(TcpListener::bind(addr));
+            let p = p.clone();
+            servers.push(thread::spawn(move || server::serve_tcp(listener, p)));
+        }
+        None => {}
+    }
+
+    if let Some(addr) = http {
+        let server = exit_on_error(tiny_http::Server::http(addr));
+        servers.push(thread::spawn(move || http::serve(server, p)));
+    }
+
+    for s in servers {
+        let _ = s.join();
+    }
+}
 
 fn main() {
     env_logger::init();
 
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() != 2 {
-        error!("Usage: {} <CSV file>", args[0]);
-        std::process::exit(1);
+    let args = Args::parse();
+
+    let config = args
+        .config
+        .as_deref()
+        .map_or_else(ProcessorConfig::default, |c| {
+            exit_on_error(ProcessorConfig::load(c))
+        });
+    let fees = args
+        .fees
+        .as_deref()
+        .map(|f| exit_on_error(FeeSchedule::load(f)));
+    let clients = args
+        .clients
+        .as_deref()
+        .map(|c| exit_on_error(clients::load(c)));
+    let limits = args
+        .limits
+        .as_deref()
+        .map(|l| exit_on_error(Limits::load(l)));
+    let fraud_rules = args
+        .fraud_rules
+        .as_deref()
+        .map(|f| exit_on_error(FraudRules::load(f)));
+    let new_processor = || {
+        let mut p = Processor::new(config.clone());
+        if let Some(clients) = &clients {
+            p.set_overdraft_limits(clients::overdraft_limits(clients));
+        }
+        if let Some(fees) = &fees {
+            p.set_fees(fees.clone());
+        }
+        if let Some(limits) = &limits {
+            p.set_limits(limits.clone());
+        }
+        if args.ordered_timestamps {
+            p.set_ordered_timestamps(true);
+        }
+        if args.wall_clock {
+            p.set_wall_clock(true);
+        }
+        if args.check_invariants {
+            p.set_check_invariants(true);
+        }
+        if let Some(rules) = &fraud_rules {
+            p.set_fraud_rules(rules.clone());
+        }
+        if let Some(days) = args.dispute_window_days {
+            p.set_dispute_window(days.saturating_mul(24 * 60 * 60));
+        }
+        if let Some(rate) = args.interest_rate {
+            p.set_interest_rate(rate, args.interest_rounding);
+        }
+        p
+    };
+
+    if args.interactive {
+        let mut r = Repl::with_processor(new_processor);
+        if let Some(path) = &args.input {
+            let mut source = exit_on_error(input::open(path, args.input_format));
+            r.load(&mut *source, |pos, e| warn!("{}: {}", pos, e));
+        }
+        exit_on_error(r.run(stdin().lock(), stdout()));
+        return;
     }
 
-    let path = &args[1];
+    if let (true, Some(path)) = (args.follow, &args.input) {
+        follow(&args, path, new_processor());
+    }
+
+    let mut p = match &args.input {
+        Some(path) => {
+            let mut source = exit_on_error(input::open(path, args.input_format));
+            let on_error = |pos: &_, e| warn!("{}: {}", pos, e);
+            if args.threads > 1 {
+                parallel::process_source(&mut *source, args.threads, new_processor, on_error)
+            } else {
+                let mut p = new_processor();
+                p.process_source(&mut *source, on_error);
+                p
+            }
+        }
+        None => new_processor(),
+    };
+
+    if args.accrue_interest {
+        // Use an id that no transaction has used yet
+        let last = p.get_history().map(|(_, t)| t.tx()).max();
+        let txn = Txn::AccrueInterest {
+            tx: last.map_or(1, |tx| tx.saturating_add(1)),
+            timestamp: None,
+        };
+        if let Err(e) = p.process_txn(&txn) {
+            warn!("{}", e);
+        }
+    }
 
-    let mut p = processor::Processor::new();
+    if args.summary {
+        eprintln!("{}", p.get_summary());
+    }
+
+    if let Some(path) = &args.flagged {
+        let mut alerts = p.get_alerts().to_vec();
+        // The shards of a multi-threaded run each have their own alerts
+        alerts.sort_by_key(|a| a.client);
+        let result = output::write_atomic(path, |w| fraud::save_alerts(w, &alerts));
+        if let Err(e) = result {
+            error!("Error while writing flagged activity: {}", e);
+            std::process::exit(1);
+        }
+    }
 
-    for e in process_csv(path.to_string(), |txn| p.process_txn(txn)) {
-        warn!("{}", e);
+    if args.listen.is_some() || args.http.is_some() {
+        serve(args.listen.as_deref(), args.http.as_deref(), p);
+        return;
     }
 
-    let accts = sorted(p.get_accounts());
-    if let Err(e) = csv_utils::save(stdout(), accts) {
-        error!("Error while writing CSV: {}", e);
+    let reconciled = match &args.reconcile {
+        Some(path) => reconcile(&args, path, &p),
+        None => {
+            if let Err(e) = write_balances(&args, &p) {
+                error!("Error while writing output: {}", e);
+                std::process::exit(1);
+            }
+            true
+        }
+    };
+    if !reconciled || !p.get_violations().is_empty() {
         std::process::exit(1);
     }
 }
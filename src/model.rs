@@ -0,0 +1,200 @@
+//! A reference model of `Processor` with the default configuration, optionally with strict
+//! disputes, written as plainly as possible, and a property test that checks that both agree on
+//! random streams of deposits, withdrawals, disputes, resolves and chargebacks.
+//!
+//! The streams use a handful of clients and transaction ids, so that they are full of
+//! duplicate ids, disputes of transactions that don't exist or belong to someone else, repeated
+//! disputes and resolves, and transactions on locked accounts.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use proptest::prelude::*;
+
+use crate::amount::Amount;
+use crate::config::{DisputePolicy, ProcessorConfig};
+use crate::processor::Processor;
+use crate::types::{Account, AccountData, ClientId, Error, Txn, TxnId};
+
+#[derive(Clone, Debug, Default)]
+struct ModelAccount {
+    available: Amount,
+    held: Amount,
+    locked: bool,
+}
+
+#[derive(Debug, Default)]
+struct Model {
+    accounts: BTreeMap<ClientId, ModelAccount>,
+    // The amounts of the deposits and withdrawals of each client
+    amounts: BTreeMap<(ClientId, TxnId), Amount>,
+    disputed: BTreeSet<(ClientId, TxnId)>,
+    // Whether `disputes.strict` is set
+    strict: bool,
+}
+
+impl Model {
+    fn process(&mut self, txn: &Txn) -> Result<(), Error> {
+        let (client, tx) = (txn.client(), txn.tx());
+        let invalid = |what: &str| Err(Error::InvalidTransaction(tx, what.to_string()));
+        let duplicate = self.amounts.contains_key(&(client, tx));
+        let acct = match (txn, self.accounts.get_mut(&client)) {
+            (_, Some(acct)) if acct.locked => return Err(Error::LockedAccount(tx, client)),
+            (Txn::Deposit { amount, .. }, None) => {
+                self.accounts.insert(
+                    client,
+                    ModelAccount {
+                        available: *amount,
+                        ..Default::default()
+                    },
+                );
+                self.amounts.insert((client, tx), *amount);
+                return Ok(());
+            }
+            (_, None) => return Err(Error::NonexistentAccount(tx, client)),
+            (_, Some(acct)) => acct,
+        };
+
+        let disputed = self.disputed.contains(&(client, tx));
+        let amount = self.amounts.get(&(client, tx)).copied();
+        match txn {
+            Txn::Deposit { .. } | Txn::Withdrawal { .. } if duplicate => {
+                if let Txn::Withdrawal { amount, .. } = txn {
+                    if *amount > acct.available {
+                        return Err(Error::InsufficientFunds(tx));
+                    }
+                }
+                return invalid("Duplicate transaction");
+            }
+            Txn::Deposit { amount, .. } => {
+                acct.available = acct.available + *amount;
+                self.amounts.insert((client, tx), *amount);
+            }
+            Txn::Withdrawal { amount, .. } => {
+                if *amount > acct.available {
+                    return Err(Error::InsufficientFunds(tx));
+                }
+                acct.available = acct.available - *amount;
+                self.amounts.insert((client, tx), *amount);
+            }
+            Txn::Dispute { .. } => match amount {
+                _ if disputed && self.strict => return invalid("Already disputed"),
+                None => return invalid("Invalid dispute"),
+                Some(amount) => {
+                    acct.available = acct.available - amount;
+                    acct.held = acct.held + amount;
+                    self.disputed.insert((client, tx));
+                }
+            },
+            Txn::Resolve { .. } => match amount {
+                Some(amount) if disputed => {
+                    acct.available = acct.available + amount;
+                    acct.held = acct.held - amount;
+                    self.disputed.remove(&(client, tx));
+                }
+                _ => return invalid("Invalid resolve"),
+            },
+            Txn::Chargeback { .. } => match amount {
+                Some(amount) if disputed || !self.strict => {
+                    acct.held = acct.held - amount;
+                    acct.locked = true;
+                    self.disputed.remove(&(client, tx));
+                }
+                _ => return invalid("Invalid chargeback"),
+            },
+            _ => unreachable!("Not generated"),
+        }
+        Ok(())
+    }
+
+    fn accounts(&self) -> BTreeMap<ClientId, Account> {
+        self.accounts
+            .iter()
+            .map(|(client, a)| {
+                let data = AccountData {
+                    client: *client,
+                    available: a.available,
+                    held: a.held,
+                };
+                let acct = if a.locked {
+                    Account::Locked(data)
+                } else {
+                    Account::Unlocked(data)
+                };
+                (*client, acct)
+            })
+            .collect()
+    }
+}
+
+fn txn() -> impl Strategy<Value = Txn> {
+    let client = 1..=4 as ClientId;
+    let tx = 1..=20 as TxnId;
+    let amount = (1..=1_000_000i64).prop_map(Amount::from);
+    prop_oneof![
+        3 => (client.clone(), tx.clone(), amount.clone()).prop_map(|(client, tx, amount)| {
+            Txn::Deposit {
+                client,
+                tx,
+                amount,
+                timestamp: None,
+            }
+        }),
+        2 => (client.clone(), tx.clone(), amount).prop_map(|(client, tx, amount)| {
+            Txn::Withdrawal {
+                client,
+                tx,
+                amount,
+                timestamp: None,
+            }
+        }),
+        2 => (client.clone(), tx.clone()).prop_map(|(client, tx)| Txn::Dispute {
+            client,
+            tx,
+            timestamp: None,
+        }),
+        1 => (client.clone(), tx.clone()).prop_map(|(client, tx)| Txn::Resolve {
+            client,
+            tx,
+            timestamp: None,
+        }),
+        1 => (client, tx).prop_map(|(client, tx)| Txn::Chargeback {
+            client,
+            tx,
+            timestamp: None,
+        }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn same_as_model(txns in prop::collection::vec(txn(), 0..200), strict in any::<bool>()) {
+        let mut p = Processor::new(ProcessorConfig {
+            disputes: DisputePolicy {
+                strict,
+                ..DisputePolicy::default()
+            },
+            ..ProcessorConfig::default()
+        });
+        p.set_check_invariants(true);
+        let mut model = Model {
+            strict,
+            ..Model::default()
+        };
+
+        for txn in &txns {
+            prop_assert_eq!(p.process_txn(txn), model.process(txn), "{}", txn);
+        }
+
+        let accounts: BTreeMap<_, _> = p
+            .get_accounts()
+            .map(|a| match a {
+                Account::Locked(d) | Account::Unlocked(d) => (d.client, a.clone()),
+            })
+            .collect();
+        prop_assert_eq!(accounts, model.accounts());
+        // Without strict disputes, disputing a transaction twice holds its amount twice
+        if strict {
+            prop_assert_eq!(p.get_violations(), &[]);
+        }
+    }
+}
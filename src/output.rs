@@ -0,0 +1,354 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use tempfile::NamedTempFile;
+
+use crate::amount::Amount;
+use crate::csv_utils::CsvWriter;
+use crate::json_utils::{JsonWriter, NdjsonWriter};
+use crate::processor::Processor;
+use crate::types::{Account, ClientId, Error};
+
+/// The end status of an account, as it is reported in the output. Amounts are rendered as
+/// numeric strings so that no output format loses precision.
+#[derive(Serialize, Debug)]
+pub struct Output {
+    pub client: ClientId,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+    /// Total fees paid, only reported if fees are charged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fees: Option<String>,
+    /// Only reported if there are overdraft limits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overdraft_limit: Option<String>,
+    /// How much more can be withdrawn with the overdraft, once the available funds are used up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining_credit: Option<String>,
+}
+
+impl Output {
+    /// The output for `acct`, including what `p` knows about it besides its balances.
+    pub fn new(acct: &Account, p: &Processor) -> Output {
+        let mut out = Output::from(acct);
+        out.fees = p.get_fees(out.client).map(|f| (&f).into());
+        if let Some(limit) = p.get_overdraft_limit(out.client) {
+            let (Account::Locked(a) | Account::Unlocked(a)) = acct;
+            let used = Amount::default().max(Amount::default() - a.available);
+            out.overdraft_limit = Some((&limit).into());
+            out.remaining_credit = Some((&Amount::default().max(limit - used)).into());
+        }
+        out
+    }
+}
+
+impl From<&Account> for Output {
+    fn from(val: &Account) -> Self {
+        match val {
+            Account::Locked(a) => Output {
+                client: a.client,
+                available: (&a.available).into(),
+                held: (&a.held).into(),
+                total: (&(a.available + a.held)).into(),
+                locked: true,
+                fees: None,
+                overdraft_limit: None,
+                remaining_credit: None,
+            },
+            Account::Unlocked(a) => Output {
+                client: a.client,
+                available: (&a.available).into(),
+                held: (&a.held).into(),
+                total: (&(a.available + a.held)).into(),
+                locked: false,
+                fees: None,
+                overdraft_limit: None,
+                remaining_credit: None,
+            },
+        }
+    }
+}
+
+/// Writes account balances in a specific format.
+pub trait AccountWriter {
+    fn write(&mut self, out: &Output) -> Result<(), Error>;
+
+    /// Writes any trailing data and flushes the underlying writer.
+    fn finish(self: Box<Self>) -> Result<(), Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    Csv,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl Format {
+    pub fn writer<'a, W: Write + 'a>(self, writer: W) -> Box<dyn AccountWriter + 'a> {
+        match self {
+            Format::Csv => Box::new(CsvWriter::new(writer)),
+            Format::Json => Box::new(JsonWriter::new(writer)),
+            Format::Ndjson => Box::new(NdjsonWriter::new(writer)),
+        }
+    }
+}
+
+pub fn save<I: IntoIterator<Item = Output>>(
+    format: Format,
+    writer: impl Write,
+    outputs: I,
+) -> Result<(), Error> {
+    let mut wrt = format.writer(writer);
+    for out in outputs {
+        wrt.write(&out)?;
+    }
+    wrt.finish()
+}
+
+/// Writes to `path` atomically: everything is written to a temporary file in the same directory,
+/// which is only renamed to `path` once `f` and the final flush succeed. If anything fails the
+/// temporary file is removed and `path` is left untouched, so readers never see a truncated file.
+///
+/// The file keeps the permissions of the file it replaces, or gets the usual ones for a new file
+/// (0666 minus the umask on Unix), rather than the private ones of a temporary file. Both the file
+/// and the directory are synced, so the new contents survive a crash once this returns.
+pub fn write_atomic<F>(path: &Path, f: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<(), Error>,
+{
+    let err = |e: String| Error::Output(path.display().to_string(), e);
+
+    // The temporary file has to be on the same filesystem as the target for the rename to be atomic
+    let dir = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    let tmp = new_temp_file(dir).map_err(|e| err(e.to_string()))?;
+    match fs::metadata(path) {
+        Ok(meta) => {
+            fs::set_permissions(tmp.path(), meta.permissions()).map_err(|e| err(e.to_string()))?
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(err(e.to_string())),
+    }
+
+    let mut wrt = BufWriter::new(tmp.as_file());
+    f(&mut wrt)?;
+    wrt.flush().map_err(|e| err(e.to_string()))?;
+    drop(wrt);
+
+    tmp.as_file().sync_all().map_err(|e| err(e.to_string()))?;
+    tmp.persist(path).map_err(|e| err(e.to_string()))?;
+    // Makes the rename itself durable
+    #[cfg(unix)]
+    File::open(dir)
+        .and_then(|d| d.sync_all())
+        .map_err(|e| err(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn new_temp_file(dir: &Path) -> io::Result<NamedTempFile> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // The mode is passed to open(2), so the umask applies as it does for any new file
+    tempfile::Builder::new()
+        .permissions(fs::Permissions::from_mode(0o666))
+        .tempfile_in(dir)
+}
+
+#[cfg(not(unix))]
+fn new_temp_file(dir: &Path) -> io::Result<NamedTempFile> {
+    NamedTempFile::new_in(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::types::{AccountData, Txn};
+
+    use super::*;
+
+    fn accounts() -> Vec<Account> {
+        vec![
+            Account::Unlocked(AccountData {
+                client: 1,
+                available: 30000.into(),
+                held: 500.into(),
+            }),
+            Account::Locked(AccountData {
+                client: 2,
+                available: (-31111).into(),
+                held: 42222.into(),
+            }),
+        ]
+    }
+
+    fn save_to_string(format: Format, accts: &[Account]) -> String {
+        let mut buf = Vec::new();
+        save(format, &mut buf, accts.iter().map(Output::from)).expect("Cannot save accounts");
+        String::from_utf8(buf).expect("Invalid utf8")
+    }
+
+    #[test]
+    fn test_save_formats() {
+        let actual = save_to_string(Format::Csv, &accounts());
+        let expected = r#"client,available,held,total,locked
+1,3.0000,0.0500,3.0500,false
+2,-3.1111,4.2222,1.1111,true
+"#;
+        assert_eq!(actual, expected);
+
+        let actual = save_to_string(Format::Json, &accounts());
+        let expected = r#"[{"client":1,"available":"3.0000","held":"0.0500","total":"3.0500","locked":false},{"client":2,"available":"-3.1111","held":"4.2222","total":"1.1111","locked":true}]
+"#;
+        assert_eq!(actual, expected);
+
+        let actual = save_to_string(Format::Ndjson, &accounts());
+        let expected = r#"{"client":1,"available":"3.0000","held":"0.0500","total":"3.0500","locked":false}
+{"client":2,"available":"-3.1111","held":"4.2222","total":"1.1111","locked":true}
+"#;
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_save_fees() {
+        let accts = accounts();
+        let outputs = || {
+            accts.iter().map(|a| Output {
+                fees: Some("0.2500".to_string()),
+                ..a.into()
+            })
+        };
+
+        let mut buf = Vec::new();
+        save(Format::Csv, &mut buf, outputs()).expect("Cannot save accounts");
+        let expected = r#"client,available,held,total,locked,fees
+1,3.0000,0.0500,3.0500,false,0.2500
+2,-3.1111,4.2222,1.1111,true,0.2500
+"#;
+        assert_eq!(String::from_utf8(buf).expect("Invalid utf8"), expected);
+
+        let mut buf = Vec::new();
+        save(Format::Ndjson, &mut buf, outputs().take(1)).expect("Cannot save accounts");
+        let expected = r#"{"client":1,"available":"3.0000","held":"0.0500","total":"3.0500","locked":false,"fees":"0.2500"}
+"#;
+        assert_eq!(String::from_utf8(buf).expect("Invalid utf8"), expected);
+    }
+
+    #[test]
+    fn test_output_overdraft() {
+        let mut p = Processor::default();
+        p.set_overdraft_limits([(1, 50000.into())].into());
+        for txn in [
+            Txn::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 10000.into(),
+                timestamp: None,
+            },
+            Txn::Withdrawal {
+                client: 1,
+                tx: 2,
+                amount: 30000.into(),
+                timestamp: None,
+            },
+        ] {
+            p.process_txn(&txn).expect("Cannot process transaction");
+        }
+
+        let acct = p.get_account(1).expect("Account not found");
+        let out = Output::new(acct, &p);
+        assert_eq!(out.overdraft_limit.as_deref(), Some("5.0000"));
+        assert_eq!(out.remaining_credit.as_deref(), Some("3.0000"));
+        assert_eq!(out.fees, None);
+    }
+
+    #[test]
+    fn test_save_empty() {
+        assert_eq!(save_to_string(Format::Csv, &[]), "");
+        assert_eq!(save_to_string(Format::Json, &[]), "[]\n");
+        assert_eq!(save_to_string(Format::Ndjson, &[]), "");
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("out.csv");
+
+        write_atomic(&path, |w| {
+            w.write_all(b"client\n1\n")
+                .map_err(|e| Error::Serialization(e.to_string()))
+        })
+        .expect("Error writing file");
+
+        let actual = fs::read_to_string(&path).expect("Cannot read file");
+        assert_eq!(actual, "client\n1\n");
+        assert_eq!(fs::read_dir(dir.path()).expect("").count(), 1);
+    }
+
+    #[test]
+    fn test_write_atomic_failure_keeps_previous_file() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("out.csv");
+        fs::write(&path, "previous\n").expect("Cannot write file");
+
+        let actual = write_atomic(&path, |w| {
+            w.write_all(b"partial")
+                .map_err(|e| Error::Serialization(e.to_string()))?;
+            Err(Error::Serialization("boom".to_string()))
+        });
+        assert_eq!(actual, Err(Error::Serialization("boom".to_string())));
+
+        let actual = fs::read_to_string(&path).expect("Cannot read file");
+        assert_eq!(actual, "previous\n");
+        assert_eq!(fs::read_dir(dir.path()).expect("").count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = |p: &Path| {
+            fs::metadata(p)
+                .expect("Cannot stat file")
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        let write = |p: &Path| {
+            write_atomic(p, |w| {
+                w.write_all(b"client\n")
+                    .map_err(|e| Error::Serialization(e.to_string()))
+            })
+            .expect("Error writing file")
+        };
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+
+        // A new file gets the same mode as one created by fs::write
+        let reference = dir.path().join("reference");
+        fs::write(&reference, "").expect("Cannot write file");
+        let path = dir.path().join("new.csv");
+        write(&path);
+        assert_eq!(mode(&path), mode(&reference));
+
+        // An existing file keeps its mode
+        let path = dir.path().join("existing.csv");
+        fs::write(&path, "previous\n").expect("Cannot write file");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).expect("Cannot chmod");
+        write(&path);
+        assert_eq!(mode(&path), 0o640);
+    }
+}
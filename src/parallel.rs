@@ -0,0 +1,461 @@
+use std::{
+    mem,
+    sync::mpsc::{sync_channel, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use crate::input::{Position, TxnSource};
+use crate::processor::{Destination, Processor};
+use crate::summary::Summary;
+use crate::types::{Error, Txn, TxnId};
+
+// Transactions are sent to the workers in batches, otherwise the channels become the bottleneck
+const BATCH_SIZE: usize = 1024;
+// Number of batches that can be queued for a worker before the reader blocks
+const QUEUE_LEN: usize = 16;
+
+// Only the line of each transaction is sent along with it. Sending the whole `Position` would
+// make all threads update the reference count of the source name.
+type Batch = Vec<(u64, Txn)>;
+
+enum Msg {
+    Batch(Batch),
+    // Replies what the destination account of a transfer needs to be checked for
+    Destination(Txn, SyncSender<Destination>),
+    // The two halves of a transfer between shards. The first one replies whether it succeeded.
+    TransferOut(u64, Txn, Destination, SyncSender<bool>),
+    TransferIn(Txn),
+    // `AccrueInterest` applies to the accounts of every worker, which all check it first. The
+    // check replies whether it would fail.
+    CheckInterest(TxnId, SyncSender<Result<(), Error>>),
+    Interest(Txn),
+}
+
+struct Shard {
+    sender: SyncSender<Msg>,
+    batch: Batch,
+    handle: JoinHandle<(Processor, Vec<(u64, Error)>)>,
+}
+
+impl Shard {
+    fn spawn(mut p: Processor) -> Shard {
+        let (sender, receiver) = sync_channel::<Msg>(QUEUE_LEN);
+        let handle = thread::spawn(move || {
+            let mut errs = Vec::new();
+            for msg in receiver {
+                match msg {
+                    Msg::Batch(batch) => {
+                        for (line, txn) in batch {
+                            if let Err(e) = p.process_txn(&txn) {
+                                errs.push((line, e));
+                            }
+                        }
+                    }
+                    Msg::Destination(txn, reply) => {
+                        let _ = reply.send(p.destination(&txn));
+                    }
+                    Msg::TransferOut(line, txn, to, reply) => {
+                        let result = p.transfer_out(&txn, to);
+                        let _ = reply.send(result.is_ok());
+                        if let Err(e) = result {
+                            errs.push((line, e));
+                        }
+                    }
+                    Msg::TransferIn(txn) => p.transfer_in(&txn),
+                    Msg::CheckInterest(tx, reply) => {
+                        let _ = reply.send(p.check_interest(tx));
+                    }
+                    Msg::Interest(txn) => {
+                        // Can't fail, all workers have checked it
+                        let _ = p.interest_in(&txn);
+                    }
+                }
+            }
+            (p, errs)
+        });
+
+        Shard {
+            sender,
+            batch: Vec::with_capacity(BATCH_SIZE),
+            handle,
+        }
+    }
+
+    fn flush(&mut self) {
+        let batch = mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+        // This can only fail if the worker is gone, in which case `finish` reports its panic
+        let _ = self.sender.send(Msg::Batch(batch));
+    }
+
+    // Sends a message that needs a reply, after everything that is queued for the worker
+    fn ask<T>(&mut self, msg: impl FnOnce(SyncSender<T>) -> Msg) -> Option<T> {
+        self.flush();
+        let (reply, receiver) = sync_channel(1);
+        let _ = self.sender.send(msg(reply));
+        receiver.recv().ok()
+    }
+
+    fn finish(mut self) -> (Processor, Vec<(u64, Error)>) {
+        self.flush();
+        drop(self.sender);
+        self.handle.join().expect("Worker thread panicked")
+    }
+}
+
+// A transfer between clients in different shards can't be applied by either worker alone. The
+// reader waits for both workers to catch up and applies it in two steps, during which neither
+// of them gets anything else to process.
+fn transfer(shards: &mut [Shard], line: u64, txn: Txn) {
+    let client = txn.counterparty().expect("Only transfers are cross-shard");
+    let from = txn.client() as usize % shards.len();
+    let to = client as usize % shards.len();
+
+    let destination = shards[to].ask(|r| Msg::Destination(txn.clone(), r));
+    let applied =
+        destination.and_then(|to| shards[from].ask(|r| Msg::TransferOut(line, txn.clone(), to, r)));
+    if applied == Some(true) {
+        let _ = shards[to].sender.send(Msg::TransferIn(txn));
+    }
+}
+
+// Like transfers, interest accruals are applied by all workers once they have caught up, so that
+// either all of them or none apply it. It's counted once, by the reader, rather than by each of
+// them.
+fn accrue_interest(shards: &mut [Shard], txn: Txn) -> Result<(), Error> {
+    for shard in shards.iter_mut() {
+        if let Some(Err(e)) = shard.ask(|r| Msg::CheckInterest(txn.tx(), r)) {
+            return Err(e);
+        }
+    }
+    for shard in shards.iter_mut() {
+        let _ = shard.sender.send(Msg::Interest(txn.clone()));
+    }
+    Ok(())
+}
+
+/// Processes all transactions in `source` with `threads` worker threads, each of them with its
+/// own `Processor` created by `new_processor`. Transactions are partitioned by client, so the
+/// transactions of any given client are still processed in order.
+///
+/// Once `source` is exhausted the state of all workers is merged into a single `Processor`, and
+/// `on_error` is called for each transaction that could not be read or applied, in the same
+/// order as `Processor::process_source` would have.
+pub fn process_source<S, N, F>(
+    source: &mut S,
+    threads: usize,
+    new_processor: N,
+    mut on_error: F,
+) -> Processor
+where
+    S: TxnSource + ?Sized,
+    N: Fn() -> Processor,
+    F: FnMut(&Position, Error),
+{
+    let threads = threads.max(1);
+    let mut shards: Vec<_> = (0..threads)
+        .map(|_| Shard::spawn(new_processor()))
+        .collect();
+    // Every worker credits the fees it collects to its own copy of the house account, so none of
+    // them knows its balance until they are merged
+    let house = new_processor().house_account().filter(|_| threads > 1);
+    let mut errs = Vec::new();
+    let mut last_pos = None;
+    // The transactions that were not processed by a single worker
+    let mut summary = Summary::default();
+
+    while let Some(item) = source.next() {
+        let pos = source.last_position();
+        match item {
+            Ok(txn @ Txn::AccrueInterest { .. }) => {
+                let outcome = accrue_interest(&mut shards, txn);
+                summary.count(&outcome);
+                if let Err(e) = outcome {
+                    errs.push((pos.clone(), e));
+                }
+                last_pos = Some(pos);
+            }
+            Ok(txn) if Some(txn.client()) == house => {
+                let outcome = Err(Error::InvalidTransaction(
+                    txn.tx(),
+                    "The house account can't have transactions with more than one thread"
+                        .to_string(),
+                ));
+                summary.count(&outcome);
+                if let Err(e) = outcome {
+                    errs.push((pos.clone(), e));
+                }
+                last_pos = Some(pos);
+            }
+            Ok(txn) if is_cross_shard(&txn, threads) => {
+                transfer(&mut shards, pos.line, txn);
+                last_pos = Some(pos);
+            }
+            Ok(txn) => {
+                let shard = &mut shards[txn.client() as usize % threads];
+                shard.batch.push((pos.line, txn));
+                if shard.batch.len() == BATCH_SIZE {
+                    shard.flush();
+                }
+                last_pos = Some(pos);
+            }
+            Err(e) => errs.push((pos, e)),
+        }
+    }
+
+    let mut merged: Option<Processor> = None;
+    for shard in shards {
+        let (p, shard_errs) = shard.finish();
+        // All transactions come from the same source, so only the line is different
+        if let Some(pos) = &last_pos {
+            errs.extend(shard_errs.into_iter().map(|(line, e)| {
+                let pos = Position {
+                    source: pos.source.clone(),
+                    line,
+                };
+                (pos, e)
+            }));
+        }
+        match merged.as_mut() {
+            Some(m) => m.merge(p),
+            None => merged = Some(p),
+        }
+    }
+
+    errs.sort_by_key(|(pos, _)| pos.line);
+    for (pos, e) in errs {
+        on_error(&pos, e);
+    }
+
+    let mut merged = merged.expect("There is always at least one shard");
+    merged.count(summary);
+    merged
+}
+
+fn is_cross_shard(txn: &Txn, threads: usize) -> bool {
+    txn.counterparty()
+        .is_some_and(|to| to as usize % threads != txn.client() as usize % threads)
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::sorted;
+
+    use crate::amount::Rounding;
+    use crate::csv_utils;
+    use crate::fees::{FeeRule, FeeSchedule};
+    use crate::generator::Rng;
+    use crate::input::MemorySource;
+    use crate::types::{Account, AccountData};
+
+    use super::*;
+
+    fn generate(count: u32, clients: u64) -> Vec<Txn> {
+        let mut rng = Rng::new(0x2545f4914f6cdd1d);
+        (1..=count)
+            .map(|tx| {
+                let client = rng.next(clients) as u16;
+                let amount = (rng.next(1_000_000) as i64).into();
+                // Disputes & co. mostly refer to earlier transactions, and often to other clients'
+                let target = rng.next(tx as u64) as u32 + 1;
+                let to = rng.next(clients) as u16;
+                match rng.next(10) {
+                    0..=3 => Txn::Deposit {
+                        client,
+                        tx,
+                        amount,
+                        timestamp: None,
+                    },
+                    4..=5 => Txn::Withdrawal {
+                        client,
+                        tx,
+                        amount,
+                        timestamp: None,
+                    },
+                    6 if to != client => Txn::Transfer {
+                        client,
+                        to,
+                        tx,
+                        amount,
+                        timestamp: None,
+                    },
+                    6 => Txn::Withdrawal {
+                        client,
+                        tx,
+                        amount,
+                        timestamp: None,
+                    },
+                    7 => Txn::Dispute {
+                        client,
+                        tx: target,
+                        timestamp: None,
+                    },
+                    8 => Txn::Resolve {
+                        client,
+                        tx: target,
+                        timestamp: None,
+                    },
+                    _ => Txn::Chargeback {
+                        client,
+                        tx: target,
+                        timestamp: None,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    // The accounts, errors and summary of a run
+    type Run = (Vec<Account>, Vec<(Position, Error)>, Summary);
+
+    fn run(txns: &[Txn], threads: usize) -> Run {
+        run_with(txns, threads, Processor::default)
+    }
+
+    fn run_with<N>(txns: &[Txn], threads: usize, new_processor: N) -> Run
+    where
+        N: Fn() -> Processor,
+    {
+        let mut src = MemorySource::new(txns.to_vec());
+        let mut errs = Vec::new();
+
+        let p = if threads == 0 {
+            let mut p = new_processor();
+            p.process_source(&mut src, |pos, e| errs.push((pos.clone(), e)));
+            p
+        } else {
+            process_source(&mut src, threads, new_processor, |pos, e| {
+                errs.push((pos.clone(), e))
+            })
+        };
+
+        let accounts = sorted(p.get_accounts().cloned()).collect();
+        (accounts, errs, p.get_summary().clone())
+    }
+
+    #[test]
+    fn same_result_as_sequential() {
+        let txns = generate(100_000, 100);
+        let expected = run(&txns, 0);
+        assert!(!expected.0.is_empty());
+        assert!(!expected.1.is_empty());
+
+        for threads in [1, 3, 8] {
+            let actual = run(&txns, threads);
+            assert_eq!(
+                actual, expected,
+                "Different result with {} threads",
+                threads
+            );
+        }
+    }
+
+    #[test]
+    fn duplicate_transfer_across_shards() {
+        let txns: Vec<_> = [
+            "deposit,1,1,10",
+            "deposit,2,2,5",
+            "dispute,2,2,",
+            "transfer,1,2,1,2",
+            "resolve,2,2,",
+        ]
+        .into_iter()
+        .map(|row| csv_utils::parse_txn(row).expect("Cannot parse"))
+        .collect();
+
+        let expected = run(&txns, 0);
+        assert_eq!(expected.1.len(), 1);
+        // Clients 1 and 2 are in different shards
+        assert_eq!(run(&txns, 2), expected);
+    }
+
+    #[test]
+    fn same_result_with_fees_and_interest() {
+        let mut txns = generate(20_000, 100);
+        for (i, tx) in (0..txns.len()).step_by(1000).rev().zip(100_000..) {
+            txns.insert(
+                i,
+                Txn::AccrueInterest {
+                    tx,
+                    timestamp: None,
+                },
+            );
+        }
+        // The house account has no transactions of its own, they would be rejected
+        let new_processor = || {
+            let mut p = Processor::default();
+            p.set_fees(FeeSchedule {
+                house_account: 1000,
+                withdrawal: Some(FeeRule {
+                    flat: 100.into(),
+                    percent: 5000.into(),
+                    ..Default::default()
+                }),
+                transfer: Some(FeeRule {
+                    flat: 200.into(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            p.set_interest_rate(1000.into(), Rounding::HalfEven);
+            p
+        };
+
+        let expected = run_with(&txns, 0, new_processor);
+        assert!(expected.0.iter().any(|a| matches!(
+            a,
+            Account::Unlocked(d) if d.client == 1000 && d.available > 0.into()
+        )));
+        // Interest accruals are counted once, although all workers apply them
+        assert_eq!(expected.2.processed, txns.len() as u64);
+        assert_eq!(run_with(&txns, 3, new_processor), expected);
+    }
+
+    #[test]
+    fn reject_house_account_transactions() {
+        let txns: Vec<_> = [
+            "deposit,1,1,10",
+            "withdrawal,1,2,1",
+            "deposit,2,3,5",
+            "withdrawal,2,4,0.5",
+            "deposit,9,5,1",
+            "withdrawal,9,6,0.0001",
+            "transfer,2,7,1,9",
+        ]
+        .into_iter()
+        .map(|row| csv_utils::parse_txn(row).expect("Cannot parse"))
+        .collect();
+        let new_processor = || {
+            let mut p = Processor::default();
+            p.set_fees(FeeSchedule {
+                house_account: 9,
+                withdrawal: Some(FeeRule {
+                    flat: 1000.into(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            p
+        };
+
+        // A single worker has the whole balance of the house account
+        let (accounts, errs, _) = run_with(&txns, 1, new_processor);
+        assert!(errs.is_empty());
+        assert!(accounts.contains(&Account::Unlocked(AccountData {
+            client: 9,
+            available: 21999.into(),
+            held: 0.into(),
+        })));
+
+        let (accounts, errs, summary) = run_with(&txns, 2, new_processor);
+        let lines: Vec<_> = errs.iter().map(|(pos, _)| pos.line).collect();
+        assert_eq!(lines, [5, 6]);
+        assert!(matches!(errs[0].1, Error::InvalidTransaction(5, _)));
+        // Transfers to the house account are still credited
+        assert!(accounts.contains(&Account::Unlocked(AccountData {
+            client: 9,
+            available: 12000.into(),
+            held: 0.into(),
+        })));
+        assert_eq!(summary.rejected("invalid_transaction"), 2);
+    }
+}
@@ -1,30 +1,638 @@
 use std::collections::HashMap;
-
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use itertools::sorted;
+
+use crate::amount::{Amount, Rounding};
+use crate::config::{DisputePolicy, LockedPolicy, ProcessorConfig};
+use crate::fees::FeeSchedule;
+use crate::fraud::{Alert, Fraud, FraudRules};
+use crate::history::{duplicate, History, Kind, Record, State};
+use crate::input::{Position, TxnSource};
+use crate::invariants::{Invariants, Violation};
+use crate::limits::Limits;
+use crate::summary::Summary;
 use crate::types::Account::{Locked, Unlocked};
-use crate::types::{Account, AccountData, ClientId, Error, Txn, TxnId};
+use crate::types::{Account, AccountData, ClientId, Error, Timestamp, Txn, TxnId};
+
+/// The state of an account before and after a transaction. `None` means that the account did
+/// not exist.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountChange {
+    pub client: ClientId,
+    pub before: Option<Account>,
+    pub after: Option<Account>,
+}
+
+/// Gets notified of every transaction processed by a `Processor`, whether it was applied or not.
+/// `changes` contains every account the transaction touches.
+pub trait TxnListener: Send {
+    fn on_txn(&mut self, txn: &Txn, outcome: &Result<(), Error>, changes: &[AccountChange]);
+}
+
+impl<F> TxnListener for F
+where
+    F: FnMut(&Txn, &Result<(), Error>, &[AccountChange]) + Send,
+{
+    fn on_txn(&mut self, txn: &Txn, outcome: &Result<(), Error>, changes: &[AccountChange]) {
+        self(txn, outcome, changes)
+    }
+}
 
-#[derive(Debug)]
 pub struct Processor {
     accounts: HashMap<ClientId, Account>,
-    history: HashMap<(TxnId, ClientId), Txn>,
-    disputes: HashMap<(TxnId, ClientId), Txn>,
+    history: History,
+    listeners: Vec<Box<dyn TxnListener>>,
+    fees: Option<FeeSchedule>,
+    // Total fees paid by each client
+    fees_paid: HashMap<ClientId, Amount>,
+    // Percentage credited by `AccrueInterest`
+    interest: Option<(Amount, Rounding)>,
+    // How far below zero the available funds of each client can go with withdrawals & transfers
+    overdraft_limits: Option<HashMap<ClientId, Amount>>,
+    limits: Option<Limits>,
+    // The latest timestamp of each client, if they must not go back in time
+    last_timestamps: Option<HashMap<ClientId, Timestamp>>,
+    // How long after a transaction it can be disputed, in seconds
+    dispute_window: Option<u64>,
+    // Whether transactions without a timestamp happen at the time they are processed
+    wall_clock: bool,
+    summary: Summary,
+    fraud: Option<Fraud>,
+    // Most decimals of the amounts
+    precision: usize,
+    dispute_policy: DisputePolicy,
+    locked_policy: LockedPolicy,
+    invariants: Option<Invariants>,
+}
+
+/// What the processor that keeps the destination account of a transfer tells the one that keeps
+/// its source, see `Processor::transfer_out`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Destination {
+    pub locked: bool,
+    // Whether it already has a transaction with the id of the transfer
+    pub duplicate: bool,
+}
+
+fn now() -> Timestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Self::new(ProcessorConfig::default())
+    }
 }
 
 impl Processor {
-    pub fn new() -> Processor {
-        Processor {
+    pub fn new(config: ProcessorConfig) -> Processor {
+        let mut p = Processor {
             accounts: HashMap::new(),
-            history: HashMap::new(),
-            disputes: HashMap::new(),
+            history: History::default(),
+            listeners: Vec::new(),
+            fees: config.fees,
+            fees_paid: HashMap::new(),
+            interest: config.interest.map(|i| (i.rate, i.rounding)),
+            overdraft_limits: None,
+            limits: config.limits,
+            last_timestamps: None,
+            dispute_window: None,
+            wall_clock: config.wall_clock,
+            summary: Summary::default(),
+            fraud: config.fraud.map(Fraud::new),
+            precision: config.precision,
+            dispute_policy: config.disputes,
+            locked_policy: config.locked_accounts,
+            invariants: None,
+        };
+        p.set_ordered_timestamps(config.ordered_timestamps);
+        p.set_check_invariants(config.check_invariants);
+        if let Some(days) = p.dispute_policy.window_days {
+            p.set_dispute_window(days.saturating_mul(24 * 60 * 60));
+        }
+        p
+    }
+
+    pub fn add_listener(&mut self, listener: Box<dyn TxnListener>) {
+        self.listeners.push(listener);
+    }
+
+    /// Charges fees according to `fees` on all transactions processed from now on.
+    pub fn set_fees(&mut self, fees: FeeSchedule) {
+        self.fees = Some(fees);
+    }
+
+    /// Makes `AccrueInterest` credit `rate` percent of the available funds of every account.
+    pub fn set_interest_rate(&mut self, rate: Amount, rounding: Rounding) {
+        self.interest = Some((rate, rounding));
+    }
+
+    /// Lets withdrawals and transfers take the available funds of each client down to minus its
+    /// limit. Clients without a limit can't go below zero.
+    pub fn set_overdraft_limits(&mut self, limits: HashMap<ClientId, Amount>) {
+        self.overdraft_limits = Some(limits);
+    }
+
+    /// Rejects deposits, withdrawals and transfers that would exceed the `limits` of their client.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = Some(limits);
+    }
+
+    /// Rejects transactions with a timestamp earlier than the latest one of the same client.
+    /// Transactions without a timestamp are not checked.
+    pub fn set_ordered_timestamps(&mut self, ordered: bool) {
+        self.last_timestamps = ordered.then(HashMap::new);
+    }
+
+    /// Rejects disputes more than `secs` seconds after the transaction they dispute. Disputes
+    /// are only checked if both transactions have a timestamp.
+    pub fn set_dispute_window(&mut self, secs: u64) {
+        self.dispute_window = Some(secs);
+    }
+
+    /// Gives transactions without a timestamp the time at which they are processed, so that the
+    /// daily and velocity limits and the time-based fraud rules apply to them. Otherwise they
+    /// are not subject to those, and processing the same input always gives the same result.
+    pub fn set_wall_clock(&mut self, wall_clock: bool) {
+        self.wall_clock = wall_clock;
+    }
+
+    /// Evaluates `rules` on every transaction applied from now on, see `get_alerts`.
+    pub fn set_fraud_rules(&mut self, rules: FraudRules) {
+        self.fraud = Some(Fraud::new(rules));
+    }
+
+    /// Checks the accounting invariants after every transaction processed from now on, see
+    /// `get_violations`. It only makes sense to turn this on before processing anything.
+    pub fn set_check_invariants(&mut self, check: bool) {
+        self.invariants = check.then(Invariants::default);
+    }
+
+    /// Forgets all accounts and transactions, but keeps the configuration and the listeners.
+    pub fn clear(&mut self) {
+        self.accounts.clear();
+        self.history.clear();
+        self.fees_paid.clear();
+        if let Some(limits) = &mut self.limits {
+            limits.clear();
+        }
+        if let Some(last) = &mut self.last_timestamps {
+            last.clear();
+        }
+        self.summary = Summary::default();
+        if let Some(fraud) = &mut self.fraud {
+            fraud.clear();
+        }
+        if self.invariants.is_some() {
+            self.invariants = Some(Invariants::default());
         }
     }
 
     pub fn process_txn(&mut self, txn: &Txn) -> Result<(), Error> {
+        let outcome = if let Txn::AccrueInterest { .. } = txn {
+            self.interest_in(txn)
+        } else {
+            let clients = [txn.client()].into_iter().chain(txn.counterparty());
+            let house = self
+                .house_account()
+                .filter(|h| !clients.clone().any(|c| c == *h));
+            self.observe(txn, clients.chain(house), |p| {
+                p.check_precision(txn)?;
+                p.check_timestamp(txn)?;
+                p.check_unique(txn)?;
+                p.with_limits(txn, |p| p.with_fee(txn, |p| p.apply(txn)))?;
+                p.record_timestamp(txn);
+                p.check_fraud(txn);
+                Ok(())
+            })
+        };
+        self.summary.count(&outcome);
+        outcome
+    }
+
+    // Whether anything needs the state of the accounts before and after each transaction
+    fn observed(&self) -> bool {
+        !self.listeners.is_empty() || self.invariants.is_some()
+    }
+
+    // Runs `f`, notifying the listeners of its outcome and of how it changed `clients`
+    fn observe<I, F>(&mut self, txn: &Txn, clients: I, f: F) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = ClientId>,
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        // Don't pay for the account snapshots if nobody is listening
+        if !self.observed() {
+            return f(self);
+        }
+
+        let before: Vec<_> = clients
+            .into_iter()
+            .map(|c| (c, self.accounts.get(&c).cloned()))
+            .collect();
+        let outcome = f(self);
+        let changes: Vec<_> = before
+            .into_iter()
+            .map(|(client, before)| AccountChange {
+                client,
+                before,
+                after: self.accounts.get(&client).cloned(),
+            })
+            .collect();
+
+        if self.invariants.is_some() {
+            let flow = self.flow(txn, &outcome, &changes);
+            let history = &self.history;
+            let disputed = |client| history.disputed(client);
+            if let Some(invariants) = &mut self.invariants {
+                invariants.check(txn, flow, &changes, disputed);
+            }
+        }
+
+        for l in self.listeners.iter_mut() {
+            l.on_txn(txn, &outcome, &changes);
+        }
+        outcome
+    }
+
+    // How much money `txn` brought into the accounts in `changes` (negative if it took it out),
+    // according to what it is rather than to how it changed them
+    fn flow(&self, txn: &Txn, outcome: &Result<(), Error>, changes: &[AccountChange]) -> Amount {
+        let zero = Amount::default();
+        if outcome.is_err() {
+            return zero;
+        }
+        match txn {
+            Txn::Deposit { amount, .. } => *amount,
+            Txn::Withdrawal { amount, .. } => zero - *amount,
+            // Either side can be kept by another processor, see `transfer_out`
+            Txn::Transfer {
+                client, to, amount, ..
+            } => changes.iter().fold(zero, |sum, c| match c.client {
+                c if c == *client => sum - *amount,
+                c if c == *to => sum + *amount,
+                _ => sum,
+            }),
+            Txn::Chargeback { client, tx, .. } => self
+                .history
+                .get(*tx, *client)
+                .map_or(zero, |r| zero - r.amount),
+            Txn::AccrueInterest { tx, .. } => changes
+                .iter()
+                .filter_map(|c| match self.history.get(*tx, c.client) {
+                    Some(r) if r.kind == Kind::Interest => Some(r.amount),
+                    _ => None,
+                })
+                .fold(zero, |sum, a| sum + a),
+            _ => zero,
+        }
+    }
+
+    /// The sending half of a transfer, for when the destination account is kept by another
+    /// processor. It checks both sides and takes the funds from the source account, and must be
+    /// followed by `transfer_in` on the other processor if it succeeds. `to` is what the other
+    /// processor's `destination` tells of the destination account.
+    pub(crate) fn transfer_out(&mut self, txn: &Txn, to: Destination) -> Result<(), Error> {
+        let house = self.house_account().filter(|h| *h != txn.client());
+        let outcome = self.observe(txn, [txn.client()].into_iter().chain(house), |p| {
+            p.check_precision(txn)?;
+            p.check_timestamp(txn)?;
+            p.check_unique(txn)?;
+            if to.duplicate {
+                return Err(duplicate(txn.tx()));
+            }
+            p.with_limits(txn, |p| {
+                p.with_fee(txn, |p| p.debit_transfer(txn, to.locked))
+            })?;
+            p.record_timestamp(txn);
+            p.check_fraud(txn);
+            Ok(())
+        });
+        self.summary.count(&outcome);
+        outcome
+    }
+
+    /// What `transfer_out` needs to know of the destination account of `txn`, a transfer.
+    pub(crate) fn destination(&self, txn: &Txn) -> Destination {
+        let to = txn.counterparty().unwrap_or(txn.client());
+        Destination {
+            locked: matches!(self.accounts.get(&to), Some(Locked(..))),
+            duplicate: self.history.contains(txn.tx(), to),
+        }
+    }
+
+    /// Fails if `AccrueInterest` with id `tx` would be rejected.
+    pub(crate) fn check_interest(&self, tx: TxnId) -> Result<(), Error> {
+        self.interest_credits(tx).map(|_| ())
+    }
+
+    /// Applies `txn`, an `AccrueInterest`, without counting it in the summary. When the accounts
+    /// are kept by several processors, it must be applied to all of them only if none of them
+    /// fails `check_interest`, and counted once.
+    pub(crate) fn interest_in(&mut self, txn: &Txn) -> Result<(), Error> {
+        let Txn::AccrueInterest { tx, timestamp } = txn else {
+            return Err(Error::InvalidTransaction(
+                txn.tx(),
+                "Not an interest accrual".to_string(),
+            ));
+        };
+        // Every account can change, don't list them all for nobody
+        if !self.observed() {
+            return self.accrue_interest(*tx, *timestamp);
+        }
+        let clients: Vec<_> = sorted(self.accounts.keys().copied()).collect();
+        self.observe(txn, clients, |p| p.accrue_interest(*tx, *timestamp))
+    }
+
+    /// The receiving half of a transfer, see `transfer_out`.
+    pub(crate) fn transfer_in(&mut self, txn: &Txn) {
+        let _ = self.observe(txn, txn.counterparty(), |p| p.credit_transfer(txn));
+    }
+
+    // The interest credited by `AccrueInterest` with id `tx` on the positive available funds of
+    // every unlocked account but the house account. Fails if any of the credited clients already
+    // has a transaction with the same id, as the credit is recorded with that id.
+    fn interest_credits(&self, tx: TxnId) -> Result<Vec<(ClientId, Amount)>, Error> {
+        let Some((rate, rounding)) = self.interest else {
+            return Err(Error::InvalidTransaction(
+                tx,
+                "No interest rate configured".to_string(),
+            ));
+        };
+
+        let house = self.house_account();
+        let credits = self
+            .accounts
+            .values()
+            .filter_map(|acct| match acct {
+                Unlocked(acct) if Some(acct.client) != house && acct.available > 0.into() => {
+                    let credit = acct.available.percent(rate, self.precision, rounding);
+                    Some((acct.client, credit))
+                }
+                _ => None,
+            })
+            .filter(|(_, amount)| *amount > 0.into())
+            .collect::<Vec<_>>();
+        if credits
+            .iter()
+            .any(|(client, _)| self.history.contains(tx, *client))
+        {
+            return Err(duplicate(tx));
+        }
+        Ok(credits)
+    }
+
+    // Credits the interest of `interest_credits`. Each credit is recorded in the history as an
+    // `Interest` transaction with id `tx`.
+    fn accrue_interest(&mut self, tx: TxnId, timestamp: Option<Timestamp>) -> Result<(), Error> {
+        // `interest_credits` has checked every id, so this can't fail halfway
+        for (client, amount) in self.interest_credits(tx)? {
+            let txn = Txn::Interest {
+                client,
+                tx,
+                amount,
+                timestamp,
+            };
+            self.history.insert(client, &txn)?;
+            if let Some(Unlocked(acct)) = self.accounts.get_mut(&client) {
+                acct.available = acct.available + amount;
+            }
+        }
+        Ok(())
+    }
+
+    fn overdraft_limit(&self, client: ClientId) -> Amount {
+        self.overdraft_limits
+            .as_ref()
+            .and_then(|l| l.get(&client).copied())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn house_account(&self) -> Option<ClientId> {
+        self.fees.as_ref().map(|f| f.house_account)
+    }
+
+    // Fails if the amount of `txn` has more decimals than allowed
+    fn check_precision(&self, txn: &Txn) -> Result<(), Error> {
+        match txn {
+            Txn::Deposit { tx, amount, .. }
+            | Txn::Withdrawal { tx, amount, .. }
+            | Txn::Transfer { tx, amount, .. }
+                if amount.decimals() > self.precision =>
+            {
+                Err(Error::InvalidTransaction(
+                    *tx,
+                    format!("Amounts can't have more than {} decimals", self.precision),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Fails if `txn` is earlier than the latest transaction of its client
+    fn check_timestamp(&self, txn: &Txn) -> Result<(), Error> {
+        let (Some(last), Some(t)) = (&self.last_timestamps, txn.timestamp()) else {
+            return Ok(());
+        };
+        match last.get(&txn.client()) {
+            Some(prev) if *prev > t => Err(Error::TimestampOutOfOrder(txn.tx(), txn.client())),
+            _ => Ok(()),
+        }
+    }
+
+    // Makes `txn`, which has just been applied, the latest transaction of its client
+    fn record_timestamp(&mut self, txn: &Txn) {
+        if let (Some(last), Some(t)) = (&mut self.last_timestamps, txn.timestamp()) {
+            last.insert(txn.client(), t);
+        }
+    }
+
+    // Fails if `txn` is a transfer and its client, or its destination, already has a transaction
+    // with the same id, as both sides must be recorded. The destination is only checked if it's
+    // kept here, see `transfer_out` otherwise. Other transactions fail when they are recorded.
+    fn check_unique(&self, txn: &Txn) -> Result<(), Error> {
+        let duplicate_of = |tx, client| self.history.contains(tx, client);
+        match txn {
+            Txn::Transfer { client, to, tx, .. }
+                if duplicate_of(*tx, *client) || duplicate_of(*tx, *to) =>
+            {
+                Err(duplicate(*tx))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Evaluates the fraud rules on `txn`, which has just been applied, locking its account if it
+    // raises an alert and the rules say so
+    fn check_fraud(&mut self, txn: &Txn) {
+        let time = self.txn_time(txn);
+        let Some(fraud) = &mut self.fraud else {
+            return;
+        };
+        if fraud.check(txn, time) && fraud.locks() {
+            if let Some(Unlocked(acct)) = self.accounts.get(&txn.client()) {
+                self.accounts.insert(txn.client(), Locked(acct.clone()));
+            }
+        }
+    }
+
+    // The time of `txn`, if it has a timestamp or the wall clock is used
+    fn txn_time(&self, txn: &Txn) -> Option<Timestamp> {
+        txn.timestamp().or_else(|| self.wall_clock.then(now))
+    }
+
+    // Runs `f` to apply `txn` if that doesn't exceed the limits of its client, and counts it
+    // towards them if it succeeds
+    fn with_limits<F>(&mut self, txn: &Txn, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        if self.limits.is_none() {
+            return f(self);
+        }
+
+        let now = self.txn_time(txn);
+        if let Some(limits) = &self.limits {
+            limits.check(txn, now)?;
+        }
+        f(self)?;
+        if let Some(limits) = &mut self.limits {
+            limits.record(txn, now);
+        }
+        Ok(())
+    }
+
+    // Runs `f` to apply `txn` and then charges its fee, if any. Withdrawals and transfers need
+    // enough funds to pay for the fee as well.
+    fn with_fee<F>(&mut self, txn: &Txn, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<(), Error>,
+    {
+        let fee = match (&self.fees, self.txn_amount(txn)) {
+            (Some(fees), Some(amount)) => fees.fee(txn, amount, self.precision),
+            _ => return f(self),
+        };
+        if fee == 0.into() {
+            return f(self);
+        }
+
+        match (txn, self.accounts.get(&txn.client())) {
+            (
+                Txn::Withdrawal { tx, amount, .. } | Txn::Transfer { tx, amount, .. },
+                Some(Unlocked(acct)),
+            ) if *amount + fee > acct.available + self.overdraft_limit(txn.client()) => {
+                return Err(Error::InsufficientFunds(*tx))
+            }
+            _ => {}
+        }
+
+        f(self)?;
+        self.charge_fee(txn.client(), fee);
+        Ok(())
+    }
+
+    // The amount a transaction moves, which is what fees are based on
+    fn txn_amount(&self, txn: &Txn) -> Option<Amount> {
+        match txn {
+            Txn::Deposit { amount, .. }
+            | Txn::Withdrawal { amount, .. }
+            | Txn::Transfer { amount, .. } => Some(*amount),
+            Txn::Chargeback { client, tx, .. } => match self.history.get(*tx, *client) {
+                Some(r) if r.kind != Kind::Interest => Some(r.amount),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // Moves `fee` from `client` to the house account. Fees are charged even if the account has
+    // just been locked by a chargeback, and the house account gets them even if it's locked.
+    fn charge_fee(&mut self, client: ClientId, fee: Amount) {
+        let Some(house) = self.house_account() else {
+            return;
+        };
+
+        if let Some(Locked(acct) | Unlocked(acct)) = self.accounts.get_mut(&client) {
+            acct.available = acct.available - fee;
+        }
+        let paid = self.fees_paid.entry(client).or_insert(0.into());
+        *paid = *paid + fee;
+
+        let acct = self.accounts.entry(house).or_insert(Unlocked(AccountData {
+            client: house,
+            available: 0.into(),
+            held: 0.into(),
+        }));
+        let (Locked(acct) | Unlocked(acct)) = acct;
+        acct.available = acct.available + fee;
+    }
+
+    fn debit_transfer(&mut self, txn: &Txn, to_locked: bool) -> Result<(), Error> {
+        let Txn::Transfer {
+            client,
+            to,
+            tx,
+            amount,
+            ..
+        } = txn
+        else {
+            return Err(Error::InvalidTransaction(
+                txn.tx(),
+                "Not a transfer".to_string(),
+            ));
+        };
+
+        let limit = self.overdraft_limit(*client);
+        match self.accounts.get_mut(client) {
+            _ if client == to => Err(Error::InvalidTransaction(
+                *tx,
+                "Transfer to the same client".to_string(),
+            )),
+
+            Some(Unlocked(..)) if to_locked => Err(Error::LockedAccount(*tx, *to)),
+
+            Some(Unlocked(acct)) if *amount <= acct.available + limit => {
+                self.history.insert(*client, txn)?;
+                acct.available = acct.available - (*amount);
+                Ok(())
+            }
+
+            Some(Unlocked(..)) => Err(Error::InsufficientFunds(*tx)),
+
+            Some(Locked(..)) => Err(Error::LockedAccount(*tx, *client)),
+
+            None => Err(Error::NonexistentAccount(*tx, *client)),
+        }
+    }
+
+    // Can't fail once `debit_transfer` has succeeded, which checks that the destination is not
+    // locked, after `check_unique` has checked that its id is unused
+    fn credit_transfer(&mut self, txn: &Txn) -> Result<(), Error> {
+        if let Txn::Transfer { to, amount, .. } = txn {
+            self.history.insert(*to, txn)?;
+            let acct = self.accounts.entry(*to).or_insert(Unlocked(AccountData {
+                client: *to,
+                available: 0.into(),
+                held: 0.into(),
+            }));
+            if let Unlocked(acct) = acct {
+                acct.available = acct.available + (*amount);
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, txn: &Txn) -> Result<(), Error> {
         match txn {
-            Txn::Deposit { client, tx, amount } => match self.accounts.get_mut(client) {
+            Txn::Deposit {
+                client, tx, amount, ..
+            } => match self.accounts.get_mut(client) {
                 Some(Unlocked(acct)) => {
+                    self.history.insert(*client, txn)?;
                     acct.available = acct.available + (*amount);
-                    self.history.insert((*tx, *client), (*txn).clone());
                     Ok(())
                 }
 
@@ -34,36 +642,83 @@ impl Processor {
                         available: *amount,
                         held: 0.into(),
                     });
+                    self.history.insert(*client, txn)?;
                     self.accounts.insert(*client, ac);
-                    self.history.insert((*tx, *client), (*txn).clone());
                     Ok(())
                 }
-                _ => Err(Error::LockedAccount(*tx, *client)),
-            },
 
-            Txn::Withdrawal { client, tx, amount } => match self.accounts.get_mut(client) {
-                Some(Unlocked(acct)) if *amount <= acct.available => {
-                    acct.available = acct.available - (*amount);
-                    self.history.insert((*tx, *client), txn.clone());
+                Some(Locked(acct)) if self.locked_policy == LockedPolicy::AcceptDeposits => {
+                    self.history.insert(*client, txn)?;
+                    acct.available = acct.available + (*amount);
                     Ok(())
                 }
 
-                Some(Unlocked(..)) => Err(Error::InsufficientFunds(*tx)),
+                _ => Err(Error::LockedAccount(*tx, *client)),
+            },
+
+            Txn::Withdrawal {
+                client, tx, amount, ..
+            } => {
+                let limit = self.overdraft_limit(*client);
+                match self.accounts.get_mut(client) {
+                    Some(Unlocked(acct)) if *amount <= acct.available + limit => {
+                        self.history.insert(*client, txn)?;
+                        acct.available = acct.available - (*amount);
+                        Ok(())
+                    }
+
+                    Some(Unlocked(..)) => Err(Error::InsufficientFunds(*tx)),
 
-                Some(Locked(..)) => Err(Error::LockedAccount(*tx, *client)),
+                    Some(Locked(..)) => Err(Error::LockedAccount(*tx, *client)),
 
-                None => Err(Error::NonexistentAccount(*tx, *client)),
-            },
+                    None => Err(Error::NonexistentAccount(*tx, *client)),
+                }
+            }
+
+            Txn::Dispute {
+                client,
+                tx,
+                timestamp,
+            } => match self.accounts.get_mut(client) {
+                Some(Unlocked(acct)) => match self.history.get(*tx, *client).copied() {
+                    Some(Record {
+                        state: State::Disputed,
+                        ..
+                    }) if self.dispute_policy.strict => Err(Error::InvalidTransaction(
+                        *tx,
+                        "Already disputed".to_string(),
+                    )),
 
-            Txn::Dispute { client, tx } => match self.accounts.get_mut(client) {
-                Some(Unlocked(acct)) => match self.history.get(&(*tx, *client)) {
                     Some(
-                        t @ Txn::Deposit { tx, amount, .. }
-                        | t @ Txn::Withdrawal { tx, amount, .. },
-                    ) => {
-                        acct.available = acct.available - (*amount);
-                        acct.held = acct.held + (*amount);
-                        self.disputes.insert((*tx, *client), (*t).clone());
+                        record @ Record {
+                            kind,
+                            state: State::Applied | State::Disputed,
+                            amount,
+                        },
+                    ) if kind != Kind::Interest => {
+                        // Only enforced if both transactions have a timestamp
+                        if let (Some(window), Some(at), Some(since)) = (
+                            self.dispute_window,
+                            *timestamp,
+                            self.history.timestamp(*tx, *client),
+                        ) {
+                            if at > since.saturating_add(window) {
+                                return Err(Error::DisputeExpired(*tx));
+                            }
+                        }
+                        // The sender of a transfer disputes it as a withdrawal
+                        if record.outgoing() && !self.dispute_policy.withdrawals {
+                            return Err(Error::InvalidTransaction(
+                                *tx,
+                                "Withdrawals can't be disputed".to_string(),
+                            ));
+                        }
+                        if amount > acct.available && !self.dispute_policy.allow_negative {
+                            return Err(Error::InsufficientFunds(*tx));
+                        }
+                        acct.available = acct.available - amount;
+                        acct.held = acct.held + amount;
+                        self.history.set_state(*tx, *client, State::Disputed);
                         Ok(())
                     }
 
@@ -78,12 +733,16 @@ impl Processor {
                 None => Err(Error::NonexistentAccount(*tx, *client)),
             },
 
-            Txn::Resolve { client, tx } => match self.accounts.get_mut(client) {
-                Some(Unlocked(acct)) => match self.disputes.get(&(*tx, *client)) {
-                    Some(Txn::Deposit { tx, amount, .. } | Txn::Withdrawal { tx, amount, .. }) => {
-                        acct.available = acct.available + (*amount);
-                        acct.held = acct.held - (*amount);
-                        self.disputes.remove(&(*tx, *client));
+            Txn::Resolve { client, tx, .. } => match self.accounts.get_mut(client) {
+                Some(Unlocked(acct)) => match self.history.get(*tx, *client).copied() {
+                    Some(Record {
+                        state: State::Disputed,
+                        amount,
+                        ..
+                    }) => {
+                        acct.available = acct.available + amount;
+                        acct.held = acct.held - amount;
+                        self.history.set_state(*tx, *client, State::Applied);
                         Ok(())
                     }
 
@@ -98,16 +757,24 @@ impl Processor {
                 None => Err(Error::NonexistentAccount(*tx, *client)),
             },
 
-            Txn::Chargeback { client, tx } => match self.accounts.get_mut(client) {
-                Some(Unlocked(acct)) => match self.history.get(&(*tx, *client)) {
-                    Some(Txn::Deposit { tx, amount, .. } | Txn::Withdrawal { tx, amount, .. }) => {
+            Txn::Chargeback { client, tx, .. } => match self.accounts.get_mut(client) {
+                Some(Unlocked(acct)) => match self.history.get(*tx, *client).copied() {
+                    Some(Record {
+                        kind,
+                        state,
+                        amount,
+                    }) if state == State::Disputed
+                        || (state == State::Applied
+                            && kind != Kind::Interest
+                            && !self.dispute_policy.strict) =>
+                    {
                         let ac = Locked(AccountData {
                             client: *client,
                             available: acct.available,
-                            held: acct.held - (*amount),
+                            held: acct.held - amount,
                         });
                         self.accounts.insert(*client, ac);
-                        self.disputes.remove(&(*tx, *client));
+                        self.history.set_state(*tx, *client, State::ChargedBack);
                         Ok(())
                     }
 
@@ -121,25 +788,152 @@ impl Processor {
 
                 None => Err(Error::NonexistentAccount(*tx, *client)),
             },
+
+            Txn::AccrueInterest { tx, timestamp } => self.accrue_interest(*tx, *timestamp),
+
+            Txn::Interest { tx, .. } => Err(Error::InvalidTransaction(
+                *tx,
+                "Interest is only credited by accrue_interest".to_string(),
+            )),
+
+            Txn::Transfer { to, .. } => {
+                let to_locked = matches!(self.accounts.get(to), Some(Locked(..)));
+                self.debit_transfer(txn, to_locked)?;
+                self.credit_transfer(txn)
+            }
+        }
+    }
+
+    /// Processes all transactions in `source`, calling `on_error` for each one that could not be
+    /// read or applied.
+    pub fn process_source<S, F>(&mut self, source: &mut S, mut on_error: F)
+    where
+        S: TxnSource + ?Sized,
+        F: FnMut(&Position, Error),
+    {
+        while let Some(item) = source.next() {
+            if let Err(e) = item.and_then(|txn| self.process_txn(&txn)) {
+                on_error(&source.last_position(), e);
+            }
+        }
+    }
+
+    /// Moves the accounts and history of `other` into this processor. Both must have seen
+    /// disjoint sets of clients, as is the case with the shards in `parallel::process_source`.
+    /// The only exception is the house account, which collects fees in every shard: its
+    /// balances are added up.
+    pub(crate) fn merge(&mut self, other: Processor) {
+        for (client, acct) in other.accounts {
+            match (self.accounts.get_mut(&client), acct) {
+                (Some(Locked(a) | Unlocked(a)), Locked(b) | Unlocked(b)) => {
+                    a.available = a.available + b.available;
+                    a.held = a.held + b.held;
+                }
+                (None, acct) => {
+                    self.accounts.insert(client, acct);
+                }
+            }
+        }
+        self.history.merge(other.history);
+        self.fees_paid.extend(other.fees_paid);
+        if let (Some(limits), Some(other)) = (&mut self.limits, other.limits) {
+            limits.merge(other);
+        }
+        if let (Some(last), Some(other)) = (&mut self.last_timestamps, other.last_timestamps) {
+            last.extend(other);
+        }
+        self.summary.merge(other.summary);
+        if let (Some(fraud), Some(other)) = (&mut self.fraud, other.fraud) {
+            fraud.merge(other);
         }
+        if let (Some(invariants), Some(other)) = (&mut self.invariants, other.invariants) {
+            invariants.merge(other);
+        }
+    }
+
+    pub fn get_account(&self, client: ClientId) -> Option<&Account> {
+        self.accounts.get(&client)
     }
 
     pub fn get_accounts(&self) -> impl Iterator<Item = &Account> {
         self.accounts.values()
     }
+
+    /// The overdraft limit of `client`, or `None` if there are no overdraft limits at all.
+    pub fn get_overdraft_limit(&self, client: ClientId) -> Option<Amount> {
+        self.overdraft_limits
+            .as_ref()
+            .map(|_| self.overdraft_limit(client))
+    }
+
+    /// How many transactions have been processed, and why they were rejected.
+    // Counts transactions that were not processed by this processor alone
+    pub(crate) fn count(&mut self, summary: Summary) {
+        self.summary.merge(summary);
+    }
+
+    pub fn get_summary(&self) -> &Summary {
+        &self.summary
+    }
+
+    /// The alerts raised by the fraud rules, in the order they were raised.
+    pub fn get_alerts(&self) -> &[Alert] {
+        self.fraud.as_ref().map_or(&[], |f| f.alerts())
+    }
+
+    /// The violations of the accounting invariants, if they are checked.
+    pub fn get_violations(&self) -> &[Violation] {
+        self.invariants.as_ref().map_or(&[], |i| i.violations())
+    }
+
+    /// The total fees paid by `client`, or `None` if no fees are charged.
+    pub fn get_fees(&self, client: ClientId) -> Option<Amount> {
+        self.fees
+            .as_ref()
+            .map(|_| self.fees_paid.get(&client).copied().unwrap_or(0.into()))
+    }
+
+    /// All transactions that changed the funds of a client, i.e. deposits, withdrawals, transfers
+    /// and interest, in no particular order, along with that client. Transfers appear once for
+    /// each side. All of them but interest can be disputed.
+    pub fn get_history(&self) -> impl Iterator<Item = (ClientId, Txn)> + '_ {
+        self.history
+            .iter()
+            .filter_map(|(tx, client, _)| Some((client, self.history.txn(tx, client)?)))
+    }
+
+    /// The transactions that are currently disputed, along with the client that disputed them.
+    pub fn get_disputes(&self) -> impl Iterator<Item = (ClientId, Txn)> + '_ {
+        self.history
+            .iter()
+            .filter(|(_, _, r)| r.state == State::Disputed)
+            .filter_map(|(tx, client, _)| Some((client, self.history.txn(tx, client)?)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use itertools::sorted;
+
+    use crate::config::InterestConfig;
+    use crate::csv_utils;
+    use crate::fees::FeeRule;
+    use crate::fraud::{DepositRule, Rule};
+    use crate::input::MemorySource;
+    use crate::limits::Limit;
+
     use super::*;
 
     #[test]
     fn deposit_creates_account() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
         let txn = Txn::Deposit {
             client: 42,
             tx: 4242,
             amount: 42.into(),
+            timestamp: None,
         };
 
         let _ = p.process_txn(&txn);
@@ -153,22 +947,114 @@ mod tests {
         let acct = p.accounts.get(&42).cloned().expect("Account not found");
         assert_eq!(acct, expected);
 
-        let hist_txn = p
-            .history
-            .get(&(4242, 42))
-            .cloned()
-            .expect("Transaction not found");
+        let hist_txn = p.history.txn(4242, 42).expect("Transaction not found");
         assert_eq!(hist_txn, txn);
     }
 
+    #[test]
+    fn process_source_reports_positions() {
+        let mut p = Processor::default();
+        let mut src = MemorySource::new(vec![
+            Txn::Deposit {
+                client: 42,
+                tx: 4242,
+                amount: 42.into(),
+                timestamp: None,
+            },
+            Txn::Withdrawal {
+                client: 42,
+                tx: 4243,
+                amount: 4200.into(),
+                timestamp: None,
+            },
+            Txn::Withdrawal {
+                client: 42,
+                tx: 4244,
+                amount: 2.into(),
+                timestamp: None,
+            },
+        ]);
+
+        let mut errs = Vec::new();
+        p.process_source(&mut src, |pos, e| errs.push((pos.line, e)));
+        assert_eq!(errs, vec![(2, Error::InsufficientFunds(4243))]);
+
+        let acct = p.accounts.get(&42).cloned().expect("Account not found");
+        assert_eq!(
+            acct,
+            Unlocked(AccountData {
+                client: 42,
+                available: 40.into(),
+                held: 0.into(),
+            })
+        );
+    }
+
+    #[test]
+    fn listeners_get_every_txn() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut p = Processor::default();
+        let evts = events.clone();
+        p.add_listener(Box::new(
+            move |txn: &Txn, outcome: &Result<(), Error>, changes: &[AccountChange]| {
+                evts.lock()
+                    .unwrap()
+                    .push((txn.clone(), outcome.clone(), changes.to_vec()))
+            },
+        ));
+
+        let deposit = Txn::Deposit {
+            client: 42,
+            tx: 4242,
+            amount: 42.into(),
+            timestamp: None,
+        };
+        let withdrawal = Txn::Withdrawal {
+            client: 42,
+            tx: 4243,
+            amount: 4200.into(),
+            timestamp: None,
+        };
+        let _ = p.process_txn(&deposit);
+        let _ = p.process_txn(&withdrawal);
+
+        let acct = Unlocked(AccountData {
+            client: 42,
+            available: 42.into(),
+            held: 0.into(),
+        });
+        let expected = vec![
+            (
+                deposit,
+                Ok(()),
+                vec![AccountChange {
+                    client: 42,
+                    before: None,
+                    after: Some(acct.clone()),
+                }],
+            ),
+            (
+                withdrawal,
+                Err(Error::InsufficientFunds(4243)),
+                vec![AccountChange {
+                    client: 42,
+                    before: Some(acct.clone()),
+                    after: Some(acct),
+                }],
+            ),
+        ];
+        assert_eq!(*events.lock().unwrap(), expected);
+    }
+
     #[test]
     fn other_txn_error_if_no_acct() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         let txn = Txn::Withdrawal {
             client: 42,
             tx: 4242,
             amount: 42.into(),
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         let expected = Err(Error::NonexistentAccount(4242, 42));
@@ -177,6 +1063,7 @@ mod tests {
         let txn = Txn::Dispute {
             client: 42,
             tx: 4242,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         assert_eq!(actual, expected);
@@ -184,6 +1071,7 @@ mod tests {
         let txn = Txn::Resolve {
             client: 42,
             tx: 4242,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         assert_eq!(actual, expected);
@@ -191,6 +1079,7 @@ mod tests {
         let txn = Txn::Chargeback {
             client: 42,
             tx: 4242,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         assert_eq!(actual, expected);
@@ -198,7 +1087,7 @@ mod tests {
 
     #[test]
     fn deposit_and_withdrawal() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         let txs = vec![
             (
@@ -206,6 +1095,7 @@ mod tests {
                     client: 42,
                     tx: 4242,
                     amount: 4242.into(),
+                    timestamp: None,
                 },
                 Unlocked(AccountData {
                     client: 42,
@@ -218,6 +1108,7 @@ mod tests {
                     client: 42,
                     tx: 4243,
                     amount: 42.into(),
+                    timestamp: None,
                 },
                 Unlocked(AccountData {
                     client: 42,
@@ -237,12 +1128,13 @@ mod tests {
 
     #[test]
     fn withdrawal_overdraft() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         let txn = Txn::Deposit {
             client: 42,
             tx: 4242,
             amount: 42.into(),
+            timestamp: None,
         };
         let _ = p.process_txn(&txn);
 
@@ -250,6 +1142,7 @@ mod tests {
             client: 42,
             tx: 4242,
             amount: 4200.into(),
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         let expected = Err(Error::InsufficientFunds(4242));
@@ -266,21 +1159,24 @@ mod tests {
 
     #[test]
     fn chargeback_locks_account() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         let txs = vec![
             Txn::Deposit {
                 client: 42,
                 tx: 4242,
                 amount: 42.into(),
+                timestamp: None,
             },
             Txn::Dispute {
                 client: 42,
                 tx: 4242,
+                timestamp: None,
             },
             Txn::Chargeback {
                 client: 42,
                 tx: 4242,
+                timestamp: None,
             },
         ];
 
@@ -300,7 +1196,7 @@ mod tests {
 
     #[test]
     fn dispute_resolution() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         let txs = vec![
             (
@@ -308,6 +1204,7 @@ mod tests {
                     client: 42,
                     tx: 4242,
                     amount: 42.into(),
+                    timestamp: None,
                 },
                 Unlocked(AccountData {
                     client: 42,
@@ -319,6 +1216,7 @@ mod tests {
                 Txn::Dispute {
                     client: 42,
                     tx: 4242,
+                    timestamp: None,
                 },
                 Unlocked(AccountData {
                     client: 42,
@@ -330,6 +1228,7 @@ mod tests {
                 Txn::Resolve {
                     client: 42,
                     tx: 4242,
+                    timestamp: None,
                 },
                 Unlocked(AccountData {
                     client: 42,
@@ -349,18 +1248,20 @@ mod tests {
 
     #[test]
     fn ignore_nonexistent_dispute() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         let txn = Txn::Deposit {
             client: 42,
             tx: 42,
             amount: 42.into(),
+            timestamp: None,
         };
         let _ = p.process_txn(&txn);
 
         let txn = Txn::Dispute {
             client: 42,
             tx: 4242,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
 
@@ -373,18 +1274,20 @@ mod tests {
 
     #[test]
     fn ignore_nonexistent_resolve() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         let txn = Txn::Deposit {
             client: 42,
             tx: 42,
             amount: 42.into(),
+            timestamp: None,
         };
         let _ = p.process_txn(&txn);
 
         let txn = Txn::Resolve {
             client: 42,
             tx: 4242,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
 
@@ -397,18 +1300,20 @@ mod tests {
 
     #[test]
     fn ignore_nonexistent_chargeback() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         let txn = Txn::Deposit {
             client: 42,
             tx: 42,
             amount: 42.into(),
+            timestamp: None,
         };
         let _ = p.process_txn(&txn);
 
         let txn = Txn::Chargeback {
             client: 42,
             tx: 4242,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
 
@@ -419,25 +1324,1027 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    fn strict_processor() -> Processor {
+        Processor::new(ProcessorConfig {
+            disputes: DisputePolicy {
+                strict: true,
+                ..DisputePolicy::default()
+            },
+            ..ProcessorConfig::default()
+        })
+    }
+
+    #[test]
+    fn strict_reject_double_dispute() {
+        let mut p = strict_processor();
+        for row in ["deposit,42,1,10", "dispute,42,1,"] {
+            let txn = csv_utils::parse_txn(row).expect("Cannot parse");
+            assert_eq!(p.process_txn(&txn), Ok(()));
+        }
+
+        let txn = csv_utils::parse_txn("dispute,42,1,").expect("Cannot parse");
+        let expected = Err(Error::InvalidTransaction(1, "Already disputed".to_string()));
+        assert_eq!(p.process_txn(&txn), expected);
+
+        // Only held once
+        let expected = Unlocked(AccountData {
+            client: 42,
+            available: 0.into(),
+            held: 100000.into(),
+        });
+        assert_eq!(p.accounts.get(&42), Some(&expected));
+    }
+
+    #[test]
+    fn strict_reject_undisputed_chargeback() {
+        let mut p = strict_processor();
+        for row in ["deposit,42,1,10", "dispute,42,1,", "resolve,42,1,"] {
+            let txn = csv_utils::parse_txn(row).expect("Cannot parse");
+            assert_eq!(p.process_txn(&txn), Ok(()));
+        }
+
+        // The dispute has been resolved
+        let txn = csv_utils::parse_txn("chargeback,42,1,").expect("Cannot parse");
+        let expected = Err(Error::InvalidTransaction(
+            1,
+            "Invalid chargeback".to_string(),
+        ));
+        assert_eq!(p.process_txn(&txn), expected);
+
+        let expected = Unlocked(AccountData {
+            client: 42,
+            available: 100000.into(),
+            held: 0.into(),
+        });
+        assert_eq!(p.accounts.get(&42), Some(&expected));
+    }
+
+    #[test]
+    fn reject_duplicate_ids() {
+        let mut p = Processor::default();
+        let txn = csv_utils::parse_txn("deposit,42,1,10").expect("Cannot parse");
+        assert_eq!(p.process_txn(&txn), Ok(()));
+
+        for row in ["deposit,42,1,5", "withdrawal,42,1,5"] {
+            let txn = csv_utils::parse_txn(row).expect("Cannot parse");
+            let expected = Err(Error::InvalidTransaction(
+                1,
+                "Duplicate transaction".to_string(),
+            ));
+            assert_eq!(p.process_txn(&txn), expected);
+        }
+
+        // Ids are unique per client
+        let txn = csv_utils::parse_txn("deposit,43,1,5").expect("Cannot parse");
+        assert_eq!(p.process_txn(&txn), Ok(()));
+        let expected = Unlocked(AccountData {
+            client: 42,
+            available: 100000.into(),
+            held: 0.into(),
+        });
+        assert_eq!(p.accounts.get(&42), Some(&expected));
+    }
+
+    #[test]
+    fn reject_duplicate_transfer() {
+        let mut p = Processor::default();
+        for row in ["deposit,1,1,10", "deposit,2,2,5", "dispute,2,2,"] {
+            let txn = csv_utils::parse_txn(row).expect("Cannot parse");
+            assert_eq!(p.process_txn(&txn), Ok(()));
+        }
+
+        // The destination already has a transaction 2, which would be lost
+        let txn = csv_utils::parse_txn("transfer,1,2,1,2").expect("Cannot parse");
+        assert_eq!(p.process_txn(&txn), Err(duplicate(2)));
+        let txn = csv_utils::parse_txn("resolve,2,2,").expect("Cannot parse");
+        assert_eq!(p.process_txn(&txn), Ok(()));
+
+        let expected = Unlocked(AccountData {
+            client: 1,
+            available: 100000.into(),
+            held: 0.into(),
+        });
+        assert_eq!(p.accounts.get(&1), Some(&expected));
+        assert_eq!(
+            p.destination(&txn),
+            Destination {
+                locked: false,
+                duplicate: true
+            }
+        );
+    }
+
+    #[test]
+    fn transfer() {
+        let mut p = Processor::default();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let evts = events.clone();
+        p.add_listener(Box::new(
+            move |_: &Txn, _: &Result<(), Error>, changes: &[AccountChange]| {
+                evts.lock().unwrap().push(changes.to_vec())
+            },
+        ));
+
+        let deposit = Txn::Deposit {
+            client: 1,
+            tx: 1,
+            amount: 10.into(),
+            timestamp: None,
+        };
+        let transfer = Txn::Transfer {
+            client: 1,
+            to: 2,
+            tx: 2,
+            amount: 4.into(),
+            timestamp: None,
+        };
+        assert_eq!(p.process_txn(&deposit), Ok(()));
+        assert_eq!(p.process_txn(&transfer), Ok(()));
+
+        let from = Unlocked(AccountData {
+            client: 1,
+            available: 6.into(),
+            held: 0.into(),
+        });
+        let to = Unlocked(AccountData {
+            client: 2,
+            available: 4.into(),
+            held: 0.into(),
+        });
+        assert_eq!(p.accounts.get(&1), Some(&from));
+        assert_eq!(p.accounts.get(&2), Some(&to));
+        assert_eq!(p.history.txn(2, 1), Some(transfer.clone()));
+        assert_eq!(p.history.txn(2, 2), Some(transfer.clone()));
+
+        // Both accounts are reported, the destination didn't exist before
+        let changes = events.lock().unwrap().pop().expect("No events");
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[1].client, &changes[1].before), (2, &None));
+        assert_eq!(changes[1].after, Some(to.clone()));
+
+        // Nothing changes if it fails
+        let txn = Txn::Transfer {
+            client: 1,
+            to: 2,
+            tx: 3,
+            amount: 7.into(),
+            timestamp: None,
+        };
+        assert_eq!(p.process_txn(&txn), Err(Error::InsufficientFunds(3)));
+        let txn = Txn::Transfer {
+            client: 3,
+            to: 1,
+            tx: 3,
+            amount: 1.into(),
+            timestamp: None,
+        };
+        assert_eq!(p.process_txn(&txn), Err(Error::NonexistentAccount(3, 3)));
+        assert_eq!(p.accounts.get(&1), Some(&from));
+        assert_eq!(p.accounts.get(&2), Some(&to));
+    }
+
+    #[test]
+    fn transfer_disputes() {
+        let mut p = Processor::default();
+        let txs = vec![
+            Txn::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 10.into(),
+                timestamp: None,
+            },
+            Txn::Transfer {
+                client: 1,
+                to: 2,
+                tx: 2,
+                amount: 4.into(),
+                timestamp: None,
+            },
+            // Each side disputes only its own account
+            Txn::Dispute {
+                client: 2,
+                tx: 2,
+                timestamp: None,
+            },
+            Txn::Chargeback {
+                client: 2,
+                tx: 2,
+                timestamp: None,
+            },
+            Txn::Dispute {
+                client: 1,
+                tx: 2,
+                timestamp: None,
+            },
+        ];
+        for txn in txs {
+            assert_eq!(p.process_txn(&txn), Ok(()));
+        }
+
+        assert_eq!(
+            p.accounts.get(&1),
+            Some(&Unlocked(AccountData {
+                client: 1,
+                available: 2.into(),
+                held: 4.into(),
+            }))
+        );
+        assert_eq!(
+            p.accounts.get(&2),
+            Some(&Locked(AccountData {
+                client: 2,
+                available: 0.into(),
+                held: 0.into(),
+            }))
+        );
+
+        // Transfers to or from a locked account are rejected
+        let txn = Txn::Transfer {
+            client: 1,
+            to: 2,
+            tx: 3,
+            amount: 1.into(),
+            timestamp: None,
+        };
+        assert_eq!(p.process_txn(&txn), Err(Error::LockedAccount(3, 2)));
+        let txn = Txn::Transfer {
+            client: 2,
+            to: 1,
+            tx: 3,
+            amount: 1.into(),
+            timestamp: None,
+        };
+        assert_eq!(p.process_txn(&txn), Err(Error::LockedAccount(3, 2)));
+    }
+
+    #[test]
+    fn fees() {
+        let mut p = Processor::default();
+        p.set_fees(FeeSchedule {
+            house_account: 0,
+            withdrawal: Some(FeeRule {
+                flat: 1.into(),
+                ..Default::default()
+            }),
+            chargeback: Some(FeeRule {
+                percent: 100000.into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let txs = vec![
+            (
+                Txn::Deposit {
+                    client: 42,
+                    tx: 1,
+                    amount: 100.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            (
+                Txn::Withdrawal {
+                    client: 42,
+                    tx: 2,
+                    amount: 50.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            // Not enough for the fee
+            (
+                Txn::Withdrawal {
+                    client: 42,
+                    tx: 3,
+                    amount: 50.into(),
+                    timestamp: None,
+                },
+                Err(Error::InsufficientFunds(3)),
+            ),
+            (
+                Txn::Dispute {
+                    client: 42,
+                    tx: 1,
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            // 10% of 100
+            (
+                Txn::Chargeback {
+                    client: 42,
+                    tx: 1,
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+        ];
+        for (txn, expected) in txs {
+            assert_eq!(p.process_txn(&txn), expected);
+        }
+
+        assert_eq!(
+            p.accounts.get(&42),
+            Some(&Locked(AccountData {
+                client: 42,
+                available: (-61).into(),
+                held: 0.into(),
+            }))
+        );
+        assert_eq!(
+            p.accounts.get(&0),
+            Some(&Unlocked(AccountData {
+                client: 0,
+                available: 11.into(),
+                held: 0.into(),
+            }))
+        );
+        assert_eq!(p.get_fees(42), Some(11.into()));
+        assert_eq!(p.get_fees(0), Some(0.into()));
+        assert_eq!(Processor::default().get_fees(42), None);
+    }
+
+    #[test]
+    fn accrue_interest() {
+        let mut p = Processor::default();
+        let txs = vec![
+            Txn::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 1234567.into(),
+                timestamp: None,
+            },
+            Txn::Deposit {
+                client: 2,
+                tx: 2,
+                amount: 10.into(),
+                timestamp: None,
+            },
+            Txn::Withdrawal {
+                client: 2,
+                tx: 3,
+                amount: 10.into(),
+                timestamp: None,
+            },
+            Txn::Deposit {
+                client: 3,
+                tx: 4,
+                amount: 10.into(),
+                timestamp: None,
+            },
+            Txn::Dispute {
+                client: 3,
+                tx: 4,
+                timestamp: None,
+            },
+            Txn::Chargeback {
+                client: 3,
+                tx: 4,
+                timestamp: None,
+            },
+        ];
+        for txn in txs {
+            assert_eq!(p.process_txn(&txn), Ok(()));
+        }
+
+        let accrue = Txn::AccrueInterest {
+            tx: 5,
+            timestamp: None,
+        };
+        assert_eq!(
+            p.process_txn(&accrue),
+            Err(Error::InvalidTransaction(
+                5,
+                "No interest rate configured".to_string()
+            ))
+        );
+
+        // 1% of 123.4567 is 1.234567
+        p.set_interest_rate(10000.into(), Rounding::HalfEven);
+        assert_eq!(p.process_txn(&accrue), Ok(()));
+
+        let interest = Txn::Interest {
+            client: 1,
+            tx: 5,
+            amount: 12346.into(),
+            timestamp: None,
+        };
+        assert_eq!(p.history.txn(5, 1), Some(interest.clone()));
+        assert_eq!(
+            p.accounts.get(&1),
+            Some(&Unlocked(AccountData {
+                client: 1,
+                available: 1246913.into(),
+                held: 0.into(),
+            }))
+        );
+        // Nothing for empty and locked accounts
+        assert!(!p.history.contains(5, 2));
+        assert!(!p.history.contains(5, 3));
+
+        // Interest can't be disputed, nor be part of the input
+        assert_eq!(
+            p.process_txn(&Txn::Dispute {
+                client: 1,
+                tx: 5,
+                timestamp: None,
+            }),
+            Err(Error::InvalidTransaction(5, "Invalid dispute".to_string()))
+        );
+        assert!(matches!(
+            p.process_txn(&interest),
+            Err(Error::InvalidTransaction(5, _))
+        ));
+    }
+
+    #[test]
+    fn accrue_interest_duplicate_ids() {
+        let mut p = Processor::default();
+        p.set_interest_rate(100000.into(), Rounding::HalfEven);
+        p.set_check_invariants(true);
+        for row in ["deposit,1,1,100", "deposit,1,3,50", "dispute,1,3,"] {
+            let txn = csv_utils::parse_txn(row).expect("Cannot parse");
+            assert_eq!(p.process_txn(&txn), Ok(()));
+        }
+
+        // Client 1 already has a transaction 3, so nothing is credited
+        let accrue = csv_utils::parse_txn("accrue_interest,,3,").expect("Cannot parse");
+        assert_eq!(p.process_txn(&accrue), Err(duplicate(3)));
+        let resolve = csv_utils::parse_txn("resolve,1,3,").expect("Cannot parse");
+        assert_eq!(p.process_txn(&resolve), Ok(()));
+        let expected = Unlocked(AccountData {
+            client: 1,
+            available: 1500000.into(),
+            held: 0.into(),
+        });
+        assert_eq!(p.accounts.get(&1), Some(&expected));
+
+        // The other way round, the id of the credit is taken
+        let accrue = csv_utils::parse_txn("accrue_interest,,4,").expect("Cannot parse");
+        assert_eq!(p.process_txn(&accrue), Ok(()));
+        let deposit = csv_utils::parse_txn("deposit,1,4,1").expect("Cannot parse");
+        assert_eq!(p.process_txn(&deposit), Err(duplicate(4)));
+        assert_eq!(p.history.get(4, 1).map(|r| r.kind), Some(Kind::Interest));
+        assert_eq!(p.get_violations(), &[]);
+    }
+
+    #[test]
+    fn overdraft() {
+        let mut p = Processor::default();
+        p.set_overdraft_limits(HashMap::from([(1, 50.into())]));
+
+        let txs = vec![
+            (
+                Txn::Deposit {
+                    client: 1,
+                    tx: 1,
+                    amount: 10.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            (
+                Txn::Withdrawal {
+                    client: 1,
+                    tx: 2,
+                    amount: 40.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            (
+                Txn::Withdrawal {
+                    client: 1,
+                    tx: 3,
+                    amount: 21.into(),
+                    timestamp: None,
+                },
+                Err(Error::InsufficientFunds(3)),
+            ),
+            (
+                Txn::Transfer {
+                    client: 1,
+                    to: 2,
+                    tx: 4,
+                    amount: 20.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            // No limit for client 2
+            (
+                Txn::Withdrawal {
+                    client: 2,
+                    tx: 5,
+                    amount: 21.into(),
+                    timestamp: None,
+                },
+                Err(Error::InsufficientFunds(5)),
+            ),
+        ];
+        for (txn, expected) in txs {
+            assert_eq!(p.process_txn(&txn), expected);
+        }
+
+        assert_eq!(
+            p.accounts.get(&1),
+            Some(&Unlocked(AccountData {
+                client: 1,
+                available: (-50).into(),
+                held: 0.into(),
+            }))
+        );
+        assert_eq!(p.get_overdraft_limit(1), Some(50.into()));
+        assert_eq!(p.get_overdraft_limit(2), Some(0.into()));
+        assert_eq!(Processor::default().get_overdraft_limit(1), None);
+    }
+
+    #[test]
+    fn limits() {
+        let mut p = Processor::default();
+        let limit = Limit {
+            max_withdrawal: Some(10.into()),
+            max_txns: Some(3),
+            window_secs: Some(3600),
+            ..Limit::default()
+        };
+        p.set_limits(Limits::new(Limit::default(), HashMap::from([(1, limit)])));
+        p.set_wall_clock(true);
+
+        let txs = vec![
+            (
+                Txn::Deposit {
+                    client: 1,
+                    tx: 1,
+                    amount: 100.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            (
+                Txn::Withdrawal {
+                    client: 1,
+                    tx: 2,
+                    amount: 11.into(),
+                    timestamp: None,
+                },
+                Err(Error::WithdrawalLimit(2)),
+            ),
+            // Failed transactions don't count towards the limits
+            (
+                Txn::Withdrawal {
+                    client: 1,
+                    tx: 3,
+                    amount: 200.into(),
+                    timestamp: None,
+                },
+                Err(Error::WithdrawalLimit(3)),
+            ),
+            (
+                Txn::Transfer {
+                    client: 1,
+                    to: 2,
+                    tx: 4,
+                    amount: 10.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            (
+                Txn::Withdrawal {
+                    client: 1,
+                    tx: 5,
+                    amount: 10.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            (
+                Txn::Deposit {
+                    client: 1,
+                    tx: 6,
+                    amount: 10.into(),
+                    timestamp: None,
+                },
+                Err(Error::VelocityLimit(6)),
+            ),
+            // No limits for client 2
+            (
+                Txn::Withdrawal {
+                    client: 2,
+                    tx: 7,
+                    amount: 10.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+        ];
+        for (txn, expected) in txs {
+            assert_eq!(p.process_txn(&txn), expected);
+        }
+        assert_eq!(
+            p.accounts.get(&1),
+            Some(&Unlocked(AccountData {
+                client: 1,
+                available: 80.into(),
+                held: 0.into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn limits_without_timestamps() {
+        let rows = [
+            "deposit,1,1,100",
+            "withdrawal,1,2,10",
+            "withdrawal,1,3,10",
+            "withdrawal,1,4,10",
+            "withdrawal,1,5,11",
+        ];
+        let run = || {
+            let mut p = Processor::default();
+            let limit = Limit {
+                max_withdrawal: Some(100000.into()),
+                max_daily_withdrawal: Some(150000.into()),
+                max_txns: Some(2),
+                window_secs: Some(3600),
+            };
+            p.set_limits(Limits::new(limit, HashMap::new()));
+            let outcomes: Vec<_> = rows
+                .iter()
+                .map(|row| p.process_txn(&csv_utils::parse_txn(row).expect("Cannot parse")))
+                .collect();
+            (
+                outcomes,
+                sorted(p.get_accounts().cloned()).collect::<Vec<_>>(),
+            )
+        };
+
+        let (outcomes, accounts) = run();
+        // Only the limit on single withdrawals applies without a time
+        assert_eq!(
+            outcomes,
+            [
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Err(Error::WithdrawalLimit(5))
+            ]
+        );
+        assert_eq!(run(), (outcomes, accounts));
+    }
+
+    #[test]
+    fn timestamps() {
+        let mut p = Processor::default();
+        p.set_ordered_timestamps(true);
+        let limit = Limit {
+            max_daily_withdrawal: Some(10.into()),
+            ..Limit::default()
+        };
+        p.set_limits(Limits::new(limit, HashMap::new()));
+
+        let day = 24 * 60 * 60;
+        let deposit = |client, tx, timestamp| Txn::Deposit {
+            client,
+            tx,
+            amount: 100.into(),
+            timestamp,
+        };
+        let withdrawal = |tx, timestamp| Txn::Withdrawal {
+            client: 1,
+            tx,
+            amount: 10.into(),
+            timestamp,
+        };
+        let txs = vec![
+            (deposit(1, 1, Some(day)), Ok(())),
+            (deposit(1, 2, Some(day)), Ok(())),
+            (
+                deposit(1, 3, Some(day - 1)),
+                Err(Error::TimestampOutOfOrder(3, 1)),
+            ),
+            // Each client has its own order
+            (deposit(2, 4, Some(day - 1)), Ok(())),
+            // Not checked
+            (deposit(1, 5, None), Ok(())),
+            (withdrawal(6, Some(day + 1)), Ok(())),
+            (withdrawal(7, Some(day + 2)), Err(Error::DailyLimit(7))),
+            // The limits go by the time of the transactions
+            (withdrawal(8, Some(2 * day)), Ok(())),
+            // Rejected transactions don't move the latest timestamp forward
+            (withdrawal(9, Some(2 * day + 1)), Err(Error::DailyLimit(9))),
+            (deposit(1, 10, Some(2 * day)), Ok(())),
+        ];
+        for (txn, expected) in txs {
+            assert_eq!(p.process_txn(&txn), expected);
+        }
+    }
+
+    #[test]
+    fn dispute_window() {
+        let mut p = Processor::default();
+        p.set_dispute_window(10);
+
+        let deposit = |tx, timestamp| Txn::Deposit {
+            client: 1,
+            tx,
+            amount: 10.into(),
+            timestamp,
+        };
+        let dispute = |tx, timestamp| Txn::Dispute {
+            client: 1,
+            tx,
+            timestamp,
+        };
+        let txs = vec![
+            (deposit(1, Some(100)), Ok(())),
+            (deposit(2, Some(100)), Ok(())),
+            (deposit(3, None), Ok(())),
+            (dispute(1, Some(110)), Ok(())),
+            (dispute(2, Some(111)), Err(Error::DisputeExpired(2))),
+            // Can't tell without both timestamps
+            (dispute(3, Some(1000)), Ok(())),
+        ];
+        for (txn, expected) in txs {
+            assert_eq!(p.process_txn(&txn), expected);
+        }
+
+        assert_eq!(p.get_summary().processed, 6);
+        assert_eq!(p.get_summary().rejected("dispute_expired"), 1);
+    }
+
+    #[test]
+    fn fraud_rules() {
+        let mut p = Processor::default();
+        p.set_fraud_rules(FraudRules {
+            lock: true,
+            withdrawal_after_deposit: Some(DepositRule {
+                min_deposit: 100.into(),
+                within_secs: 60,
+            }),
+            ..FraudRules::default()
+        });
+
+        let txs = vec![
+            Txn::Deposit {
+                client: 1,
+                tx: 1,
+                amount: 100.into(),
+                timestamp: Some(1000),
+            },
+            Txn::Withdrawal {
+                client: 1,
+                tx: 2,
+                amount: 10.into(),
+                timestamp: Some(1059),
+            },
+        ];
+        for txn in &txs {
+            // The transaction itself is still applied
+            assert_eq!(p.process_txn(txn), Ok(()));
+        }
+
+        assert_eq!(
+            p.accounts.get(&1),
+            Some(&Locked(AccountData {
+                client: 1,
+                available: 90.into(),
+                held: 0.into(),
+            }))
+        );
+        assert_eq!(
+            p.get_alerts(),
+            &[Alert {
+                client: 1,
+                tx: 2,
+                rule: Rule::WithdrawalAfterDeposit,
+                time: Some(1059),
+            }]
+        );
+        assert!(Processor::default().get_alerts().is_empty());
+    }
+
+    #[test]
+    fn fraud_without_timestamps() {
+        let rows = ["deposit,1,1,100", "withdrawal,1,2,10", "withdrawal,1,3,10"];
+        let run = || {
+            let mut p = Processor::default();
+            p.set_fraud_rules(FraudRules {
+                lock: true,
+                withdrawal_after_deposit: Some(DepositRule {
+                    min_deposit: 100.into(),
+                    within_secs: 3600,
+                }),
+                ..FraudRules::default()
+            });
+            for row in rows {
+                let txn = csv_utils::parse_txn(row).expect("Cannot parse");
+                assert_eq!(p.process_txn(&txn), Ok(()));
+            }
+            let accounts: Vec<_> = sorted(p.get_accounts().cloned()).collect();
+            (accounts, p.get_alerts().to_vec())
+        };
+
+        // The time-based rules don't apply without a time, so the accounts are not locked
+        let (accounts, alerts) = run();
+        assert!(alerts.is_empty());
+        assert!(matches!(accounts[..], [Unlocked(..)]));
+        assert_eq!(run(), (accounts, alerts));
+    }
+
+    #[test]
+    fn policies() {
+        let mut p = Processor::new(ProcessorConfig {
+            precision: 2,
+            disputes: DisputePolicy {
+                withdrawals: false,
+                allow_negative: false,
+                window_days: None,
+                strict: false,
+            },
+            locked_accounts: LockedPolicy::AcceptDeposits,
+            ..ProcessorConfig::default()
+        });
+
+        let deposit = |tx, amount: i64| Txn::Deposit {
+            client: 1,
+            tx,
+            amount: amount.into(),
+            timestamp: None,
+        };
+        let dispute = |tx| Txn::Dispute {
+            client: 1,
+            tx,
+            timestamp: None,
+        };
+        let txs = vec![
+            (deposit(1, 10100), Ok(())),
+            (
+                deposit(2, 10010),
+                Err(Error::InvalidTransaction(
+                    2,
+                    "Amounts can't have more than 2 decimals".to_string(),
+                )),
+            ),
+            (
+                Txn::Withdrawal {
+                    client: 1,
+                    tx: 3,
+                    amount: 5000.into(),
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            (
+                dispute(3),
+                Err(Error::InvalidTransaction(
+                    3,
+                    "Withdrawals can't be disputed".to_string(),
+                )),
+            ),
+            // Only 0.51 are available
+            (dispute(1), Err(Error::InsufficientFunds(1))),
+            (deposit(4, 20000), Ok(())),
+            (dispute(1), Ok(())),
+            (
+                Txn::Chargeback {
+                    client: 1,
+                    tx: 1,
+                    timestamp: None,
+                },
+                Ok(()),
+            ),
+            // Deposits still go through once locked
+            (deposit(5, 10000), Ok(())),
+            (dispute(5), Err(Error::LockedAccount(5, 1))),
+        ];
+        for (txn, expected) in txs {
+            assert_eq!(p.process_txn(&txn), expected);
+        }
+
+        assert_eq!(
+            p.accounts.get(&1),
+            Some(&Locked(AccountData {
+                client: 1,
+                available: 25000.into(),
+                held: 0.into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn precision_of_fees_and_interest() {
+        let mut p = Processor::new(ProcessorConfig {
+            precision: 2,
+            interest: Some(InterestConfig {
+                rate: 5000.into(),
+                rounding: Rounding::HalfUp,
+            }),
+            fees: Some(FeeSchedule {
+                house_account: 9,
+                withdrawal: Some(FeeRule {
+                    percent: 10000.into(),
+                    ..FeeRule::default()
+                }),
+                ..FeeSchedule::default()
+            }),
+            ..ProcessorConfig::default()
+        });
+
+        for row in [
+            "deposit,1,1,12.34",
+            "withdrawal,1,2,1.23",
+            "accrue_interest,,3,",
+        ] {
+            let txn = csv_utils::parse_txn(row).expect("Cannot parse");
+            assert_eq!(p.process_txn(&txn), Ok(()), "{}", row);
+        }
+
+        // A fee of 0.0123 is rounded to 0.01, and interest of 0.0555 on the remaining 11.10 to 0.06
+        assert_eq!(p.get_fees(1), Some(100.into()));
+        assert_eq!(
+            p.accounts.get(&1),
+            Some(&Unlocked(AccountData {
+                client: 1,
+                available: 111600.into(),
+                held: 0.into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn invariants() {
+        let mut p = Processor::default();
+        p.set_check_invariants(true);
+        p.set_fees(FeeSchedule {
+            house_account: 9,
+            withdrawal: Some(FeeRule {
+                flat: 1.into(),
+                ..FeeRule::default()
+            }),
+            ..FeeSchedule::default()
+        });
+        p.set_interest_rate(10000.into(), Rounding::HalfEven);
+
+        let txs = [
+            "deposit,1,1,10",
+            "deposit,2,2,5",
+            "withdrawal,1,3,1",
+            "withdrawal,1,4,100",
+            "transfer,1,5,2,2",
+            "dispute,2,5",
+            "resolve,2,5",
+            "dispute,1,1",
+            "accrue_interest,,6,",
+            "chargeback,1,1",
+        ];
+        for row in txs {
+            let txn = csv_utils::parse_txn(row).expect("Cannot parse");
+            let _ = p.process_txn(&txn);
+        }
+        assert_eq!(p.get_violations(), &[]);
+
+        // Funds held out of nowhere
+        if let Some(Unlocked(acct)) = p.accounts.get_mut(&2) {
+            acct.held = acct.held + 1.into();
+        }
+        let deposit = csv_utils::parse_txn("deposit,2,7,1").expect("Cannot parse");
+        assert_eq!(p.process_txn(&deposit), Ok(()));
+        let violations: Vec<_> = p.get_violations().iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            violations,
+            vec![
+                "Transaction 7 (`deposit,2,7,1.0000`) breaks `held = open disputes`: client 2 \
+                 holds 0.0001, but its open disputes are for 0.0000"
+            ]
+        );
+    }
+
     #[test]
     fn locked_accounts() {
-        let mut p = Processor::new();
+        let mut p = Processor::default();
 
         // Set the account to a Locked state
         let txn = Txn::Deposit {
             client: 42,
             tx: 4242,
             amount: 42.into(),
+            timestamp: None,
         };
         let _ = p.process_txn(&txn);
         let txn = Txn::Dispute {
             client: 42,
             tx: 4242,
+            timestamp: None,
         };
         let _ = p.process_txn(&txn);
         let txn = Txn::Chargeback {
             client: 42,
             tx: 4242,
+            timestamp: None,
         };
         let _ = p.process_txn(&txn);
 
@@ -456,6 +2363,7 @@ mod tests {
             client: 42,
             tx: 4243,
             amount: 42.into(),
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         assert_eq!(actual, expected);
@@ -464,6 +2372,7 @@ mod tests {
             client: 42,
             tx: 4243,
             amount: 42.into(),
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         assert_eq!(actual, expected);
@@ -471,6 +2380,7 @@ mod tests {
         let txn = Txn::Dispute {
             client: 42,
             tx: 4243,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         assert_eq!(actual, expected);
@@ -478,6 +2388,7 @@ mod tests {
         let txn = Txn::Resolve {
             client: 42,
             tx: 4243,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         assert_eq!(actual, expected);
@@ -485,6 +2396,7 @@ mod tests {
         let txn = Txn::Chargeback {
             client: 42,
             tx: 4243,
+            timestamp: None,
         };
         let actual = p.process_txn(&txn);
         assert_eq!(actual, expected);
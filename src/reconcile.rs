@@ -0,0 +1,210 @@
+//! Comparison of the final accounts against the balances expected by another system, in the same
+//! CSV format as the output, e.g.:
+//!
+//! ```csv
+//! client,available,held,total,locked
+//! 1,1.5,0,1.5,false
+//! ```
+//!
+//! Only the columns in the expected file are compared, so it can leave out e.g. `total`, or have
+//! the extra columns of the output such as `fees`. Amounts are compared by value, so `1.5` matches
+//! `1.5000`.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    path::Path,
+};
+
+use crate::amount::Amount;
+use crate::output::Output;
+use crate::types::{ClientId, Error};
+
+/// The expected columns of each client, by name.
+pub type Expected = BTreeMap<ClientId, BTreeMap<String, String>>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Difference {
+    /// Expected, but there is no such account
+    Missing(ClientId),
+    /// An account that was not expected
+    Extra(ClientId),
+    Field {
+        client: ClientId,
+        field: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difference::Missing(client) => write!(f, "missing client {}", client),
+            Difference::Extra(client) => write!(f, "extra client {}", client),
+            Difference::Field {
+                client,
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "client {}: {} is `{}`, expected `{}`",
+                client, field, actual, expected
+            ),
+        }
+    }
+}
+
+pub fn load_expected(path: &Path) -> Result<Expected, Error> {
+    let name = path.display().to_string();
+    let err = |e: String| Error::Deserialization(name.clone(), e);
+    let mut rdr = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| err(e.to_string()))?;
+    let headers = rdr.headers().map_err(|e| err(e.to_string()))?.clone();
+    if !headers.iter().any(|h| h == "client") {
+        return Err(err("Missing client column".to_string()));
+    }
+
+    let mut expected = Expected::new();
+    for record in rdr.records() {
+        let record = record.map_err(|e| err(e.to_string()))?;
+        let mut fields: BTreeMap<_, _> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(h, v)| (h.to_string(), v.to_string()))
+            .collect();
+        let client = fields.remove("client").unwrap_or_default();
+        let client: ClientId = client
+            .parse()
+            .map_err(|_| err(format!("Invalid client: {}", client)))?;
+        if expected.insert(client, fields).is_some() {
+            return Err(err(format!("Duplicate client: {}", client)));
+        }
+    }
+    Ok(expected)
+}
+
+// Whether two values of a field are the same, comparing amounts by value
+fn same(expected: &str, actual: &str) -> bool {
+    match (
+        Amount::try_from(expected.to_string()),
+        Amount::try_from(actual.to_string()),
+    ) {
+        (Ok(e), Ok(a)) => e == a,
+        _ => expected == actual,
+    }
+}
+
+/// All differences between `expected` and the `actual` accounts, sorted by client.
+pub fn reconcile<I: IntoIterator<Item = Output>>(
+    expected: &Expected,
+    actual: I,
+) -> Vec<Difference> {
+    let mut diffs = Vec::new();
+    let mut seen = HashSet::new();
+
+    for out in actual {
+        let Some(fields) = expected.get(&out.client) else {
+            diffs.push(Difference::Extra(out.client));
+            continue;
+        };
+        seen.insert(out.client);
+
+        // The output has no nested values, so this is a flat object of strings, numbers and bools
+        let values = match serde_json::to_value(&out) {
+            Ok(serde_json::Value::Object(values)) => values,
+            _ => Default::default(),
+        };
+        for (field, exp) in fields {
+            let actual = match values.get(field) {
+                Some(serde_json::Value::String(s)) => s.clone(),
+                Some(v) => v.to_string(),
+                None => String::new(),
+            };
+            if !same(exp, &actual) {
+                diffs.push(Difference::Field {
+                    client: out.client,
+                    field: field.clone(),
+                    expected: exp.clone(),
+                    actual,
+                });
+            }
+        }
+    }
+
+    diffs.extend(
+        expected
+            .keys()
+            .filter(|c| !seen.contains(c))
+            .map(|c| Difference::Missing(*c)),
+    );
+    diffs.sort_by_key(|d| match d {
+        Difference::Missing(c) | Difference::Extra(c) | Difference::Field { client: c, .. } => *c,
+    });
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::types::{Account, AccountData};
+
+    use super::*;
+
+    #[test]
+    fn test_reconcile() {
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("expected.csv");
+        fs::write(
+            &path,
+            "client,available,held,locked\n1,1.5,0,false\n2,2,0,false\n3,0,0,false\n",
+        )
+        .expect("Cannot write file");
+        let expected = load_expected(&path).expect("Cannot load expected balances");
+
+        let accounts = [
+            Account::Unlocked(AccountData {
+                client: 1,
+                available: 15000.into(),
+                held: 0.into(),
+            }),
+            Account::Locked(AccountData {
+                client: 2,
+                available: 10000.into(),
+                held: 0.into(),
+            }),
+            Account::Unlocked(AccountData {
+                client: 4,
+                available: 0.into(),
+                held: 0.into(),
+            }),
+        ];
+        let diffs = reconcile(&expected, accounts.iter().map(Output::from));
+        let report: Vec<_> = diffs.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            report,
+            vec![
+                "client 2: available is `1.0000`, expected `2`",
+                "client 2: locked is `true`, expected `false`",
+                "missing client 3",
+                "extra client 4",
+            ]
+        );
+
+        for bad in [
+            "available\n1\n",
+            "client,available\nx,1\n",
+            "client,available\n1,1\n1,2\n",
+        ] {
+            fs::write(&path, bad).expect("Cannot write file");
+            assert!(matches!(
+                load_expected(&path),
+                Err(Error::Deserialization(..))
+            ));
+        }
+    }
+}
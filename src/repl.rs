@@ -0,0 +1,282 @@
+//! An interactive mode for operators to enter transactions and inspect accounts.
+
+use std::io::{self, BufRead, Write};
+
+use itertools::Itertools;
+
+use crate::csv_utils;
+use crate::input::{Position, TxnSource};
+use crate::json_utils;
+use crate::output::Output;
+use crate::processor::Processor;
+use crate::types::{ClientId, Error, Txn};
+
+const HELP: &str = "\
+<type>,<client>,<tx>[,<amount>[,<to>]]  Process a transaction, e.g. `deposit,1,1,1.5` (JSON also works)
+account <client>                        Show an account
+history <client>                        List the deposits, withdrawals and transfers of a client
+disputes <client>                       List the open disputes of a client
+undo                                    Revert the last transaction that was applied
+help                                    Show this help
+quit                                    Exit";
+
+pub struct Repl<'a> {
+    p: Processor,
+    // Creates the processor that undo replays the journal on
+    new_processor: Box<dyn Fn() -> Processor + 'a>,
+    // All applied transactions, so that undo can replay all but the last one
+    journal: Vec<Txn>,
+}
+
+impl Default for Repl<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Repl<'a> {
+    pub fn new() -> Repl<'a> {
+        Repl::with_processor(Processor::default)
+    }
+
+    /// Creates a session on a processor from `new_processor`, which must always create
+    /// processors with the same configuration for undo to work.
+    pub fn with_processor<N: Fn() -> Processor + 'a>(new_processor: N) -> Repl<'a> {
+        Repl {
+            p: new_processor(),
+            new_processor: Box::new(new_processor),
+            journal: Vec::new(),
+        }
+    }
+
+    /// Processes all transactions in `source`. They can also be undone.
+    pub fn load<S, F>(&mut self, source: &mut S, mut on_error: F)
+    where
+        S: TxnSource + ?Sized,
+        F: FnMut(&Position, Error),
+    {
+        while let Some(item) = source.next() {
+            if let Err(e) = item.and_then(|txn| self.apply(txn)) {
+                on_error(&source.last_position(), e);
+            }
+        }
+    }
+
+    /// Reads commands from `input` until it is exhausted or the user quits.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+
+        for line in input.lines() {
+            let line = line?;
+            match line.trim() {
+                "quit" | "exit" => return Ok(()),
+                "" => {}
+                cmd => match self.execute(cmd) {
+                    Ok(s) => writeln!(output, "{}", s)?,
+                    Err(e) => writeln!(output, "error: {}", e)?,
+                },
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    /// Executes a single command, returning what should be shown to the user.
+    pub fn execute(&mut self, cmd: &str) -> Result<String, Error> {
+        let (name, arg) = cmd
+            .split_once(char::is_whitespace)
+            .map_or((cmd, ""), |(n, a)| (n, a.trim()));
+
+        match name {
+            "help" => Ok(HELP.to_string()),
+            "undo" => self.undo(),
+            "account" => {
+                let client = parse_client(arg)?;
+                let acct = self
+                    .p
+                    .get_account(client)
+                    .ok_or(Error::UnknownClient(client))?;
+                csv_utils::to_csv(&Output::new(acct, &self.p), true)
+            }
+            "history" => {
+                let client = parse_client(arg)?;
+                let txns = self.p.get_history().filter(|(c, _)| *c == client);
+                Ok(list(txns))
+            }
+            "disputes" => {
+                let client = parse_client(arg)?;
+                let txns = self.p.get_disputes().filter(|(c, _)| *c == client);
+                Ok(list(txns))
+            }
+            _ => {
+                let txn = if cmd.starts_with('{') {
+                    json_utils::parse_txn(cmd)?
+                } else {
+                    csv_utils::parse_txn(cmd)?
+                };
+                self.apply(txn).map(|_| "ok".to_string())
+            }
+        }
+    }
+
+    fn apply(&mut self, txn: Txn) -> Result<(), Error> {
+        self.p.process_txn(&txn)?;
+        self.journal.push(txn);
+        Ok(())
+    }
+
+    fn undo(&mut self) -> Result<String, Error> {
+        let (txn, rest) = self
+            .journal
+            .split_last()
+            .ok_or(Error::Input("Nothing to undo".to_string()))?;
+
+        // Replaying the journal gets us to the previous state, unless a transaction depends on
+        // the time it's processed at (e.g. limits), in which case nothing is undone
+        let mut p = (self.new_processor)();
+        for t in rest {
+            p.process_txn(t)
+                .map_err(|e| Error::Input(format!("Cannot undo, replaying {} fails: {}", t, e)))?;
+        }
+        let undone = format!("undone: {}", txn);
+        self.p = p;
+        self.journal.pop();
+        Ok(undone)
+    }
+
+    pub fn processor(&self) -> &Processor {
+        &self.p
+    }
+}
+
+fn parse_client(arg: &str) -> Result<ClientId, Error> {
+    arg.parse()
+        .map_err(|e| Error::Input(format!("Invalid client {}: {}", arg, e)))
+}
+
+fn list(txns: impl Iterator<Item = (ClientId, Txn)>) -> String {
+    let rows = txns.map(|(_, t)| t).sorted_by_key(|t| t.tx()).join("\n");
+    if rows.is_empty() {
+        "(none)".to_string()
+    } else {
+        format!("type,client,tx,amount,to,timestamp\n{}", rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::config::ProcessorConfig;
+
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let mut r = Repl::new();
+
+        assert_eq!(r.execute("deposit,1,1,10"), Ok("ok".to_string()));
+        assert_eq!(
+            r.execute(r#"{"type":"deposit","client":1,"tx":2,"amount":"5"}"#),
+            Ok("ok".to_string())
+        );
+        assert_eq!(r.execute("deposit,2,3,1"), Ok("ok".to_string()));
+        assert_eq!(
+            r.execute("withdrawal,1,4,100"),
+            Err(Error::InsufficientFunds(4))
+        );
+        assert_eq!(r.execute("dispute,1,1"), Ok("ok".to_string()));
+
+        assert_eq!(
+            r.execute("account 1"),
+            Ok("client,available,held,total,locked\n1,5.0000,10.0000,15.0000,false".to_string())
+        );
+        assert_eq!(r.execute("account 3"), Err(Error::UnknownClient(3)));
+        assert!(matches!(r.execute("account x"), Err(Error::Input(_))));
+        assert_eq!(
+            r.execute("history 1"),
+            Ok(
+                "type,client,tx,amount,to,timestamp\ndeposit,1,1,10.0000\ndeposit,1,2,5.0000"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            r.execute("disputes  1"),
+            Ok("type,client,tx,amount,to,timestamp\ndeposit,1,1,10.0000".to_string())
+        );
+        assert_eq!(r.execute("disputes 2"), Ok("(none)".to_string()));
+        assert!(matches!(r.execute("bogus"), Err(Error::Input(_))));
+
+        assert_eq!(r.execute("transfer,2,5,0.5,3"), Ok("ok".to_string()));
+        assert_eq!(
+            r.execute("history 3"),
+            Ok("type,client,tx,amount,to,timestamp\ntransfer,2,5,0.5000,3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut r = Repl::new();
+
+        assert_eq!(r.execute("deposit,1,1,10"), Ok("ok".to_string()));
+        assert_eq!(r.execute("dispute,1,1"), Ok("ok".to_string()));
+        // Rejected transactions are not undone
+        assert_eq!(
+            r.execute("dispute,1,7"),
+            Err(Error::InvalidTransaction(7, "Invalid dispute".to_string()))
+        );
+
+        assert_eq!(r.execute("undo"), Ok("undone: dispute,1,1,".to_string()));
+        assert_eq!(r.execute("disputes 1"), Ok("(none)".to_string()));
+        assert_eq!(
+            r.execute("account 1"),
+            Ok("client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false".to_string())
+        );
+
+        assert_eq!(
+            r.execute("undo"),
+            Ok("undone: deposit,1,1,10.0000".to_string())
+        );
+        assert_eq!(r.execute("account 1"), Err(Error::UnknownClient(1)));
+        assert!(matches!(r.execute("undo"), Err(Error::Input(_))));
+    }
+
+    #[test]
+    fn test_failed_undo() {
+        // The processors created for undo reject what the first one accepted
+        let created = Cell::new(0);
+        let mut r = Repl::with_processor(|| {
+            created.set(created.get() + 1);
+            let precision = if created.get() > 1 { 0 } else { 4 };
+            Processor::new(ProcessorConfig {
+                precision,
+                ..Default::default()
+            })
+        });
+
+        assert_eq!(r.execute("deposit,1,1,1.5"), Ok("ok".to_string()));
+        assert_eq!(r.execute("deposit,1,2,1"), Ok("ok".to_string()));
+        assert!(matches!(r.execute("undo"), Err(Error::Input(_))));
+        // Nothing was undone, not even from the journal
+        assert!(matches!(r.execute("undo"), Err(Error::Input(_))));
+        assert_eq!(
+            r.execute("account 1"),
+            Ok("client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false".to_string())
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let mut r = Repl::new();
+        let input = "deposit,1,1,10\n\nwithdrawal,1,2,20\nquit\ndeposit,1,3,1\n";
+        let mut output = Vec::new();
+
+        r.run(input.as_bytes(), &mut output).expect("Error running");
+
+        let expected = "> ok\n> > error: Transaction 2: Insufficient funds\n> ";
+        assert_eq!(String::from_utf8(output).expect("Invalid utf8"), expected);
+        assert_eq!(r.processor().get_accounts().count(), 1);
+    }
+}
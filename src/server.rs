@@ -0,0 +1,191 @@
+//! A line-based protocol to process transactions and query accounts over a socket.
+//!
+//! Every non-blank line sent by a client gets exactly one line back:
+//!
+//! * A transaction, either as a CSV row without a header (`deposit,1,1,1.5`) or as a JSON object
+//!   (`{"type":"deposit","client":1,"tx":1,"amount":"1.5"}`). The reply is `ok` if it was
+//!   applied, or `error <code> <message>` otherwise.
+//! * `query <client>`. The reply is `ok <client>,<available>,<held>,<total>,<locked>`, i.e. the
+//!   same row the CSV output would contain for that account, or `error <code> <message>`.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use log::{info, warn};
+
+use crate::csv_utils;
+use crate::json_utils;
+use crate::output::Output;
+use crate::processor::Processor;
+use crate::types::{ClientId, Error};
+
+/// Accepts connections on `listener` forever, serving each of them on its own thread. All
+/// connections share the same `Processor`.
+pub fn serve_tcp(listener: TcpListener, p: Arc<Mutex<Processor>>) {
+    for stream in listener.incoming() {
+        match stream.and_then(|s| Ok((s.try_clone()?, s))) {
+            Ok((reader, writer)) => spawn_handler(reader, writer, p.clone()),
+            Err(e) => warn!("Error accepting connection: {}", e),
+        }
+    }
+}
+
+/// Same as `serve_tcp`, but for a Unix socket.
+#[cfg(unix)]
+pub fn serve_unix(listener: std::os::unix::net::UnixListener, p: Arc<Mutex<Processor>>) {
+    for stream in listener.incoming() {
+        match stream.and_then(|s| Ok((s.try_clone()?, s))) {
+            Ok((reader, writer)) => spawn_handler(reader, writer, p.clone()),
+            Err(e) => warn!("Error accepting connection: {}", e),
+        }
+    }
+}
+
+fn spawn_handler<R, W>(reader: R, writer: W, p: Arc<Mutex<Processor>>)
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        if let Err(e) = handle(BufReader::new(reader), writer, &p) {
+            warn!("Error in connection: {}", e);
+        }
+    });
+}
+
+/// Serves a single connection until the client closes it.
+pub fn handle<R: BufRead, W: Write>(
+    reader: R,
+    mut writer: W,
+    p: &Mutex<Processor>,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let reply = match respond(line, p) {
+            Ok(r) => r,
+            Err(e) => {
+                info!("{}: {}", line, e);
+                format!("error {} {}", e.code(), e)
+            }
+        };
+        writeln!(writer, "{}", reply)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn respond(line: &str, p: &Mutex<Processor>) -> Result<String, Error> {
+    if let Some(client) = line.strip_prefix("query ") {
+        let client: ClientId = client
+            .trim()
+            .parse()
+            .map_err(|e| Error::Input(format!("Invalid client {}: {}", client.trim(), e)))?;
+        let p = p.lock().expect("Processor lock poisoned");
+        return match p.get_account(client) {
+            Some(acct) => Ok(format!("ok {}", csv_utils::to_row(&Output::new(acct, &p))?)),
+            None => Err(Error::UnknownClient(client)),
+        };
+    }
+
+    let txn = if line.starts_with('{') {
+        json_utils::parse_txn(line)?
+    } else {
+        csv_utils::parse_txn(line)?
+    };
+    p.lock()
+        .expect("Processor lock poisoned")
+        .process_txn(&txn)
+        .map(|_| "ok".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+
+    // Sends every request through a real socket and returns the replies
+    fn script<S: Read + Write>(mut stream: S, requests: &[&str]) -> Vec<String> {
+        for r in requests {
+            writeln!(stream, "{}", r).expect("Cannot write request");
+        }
+        let mut replies = Vec::new();
+        let mut rdr = BufReader::new(stream);
+        for _ in requests.iter().filter(|r| !r.trim().is_empty()) {
+            let mut reply = String::new();
+            rdr.read_line(&mut reply).expect("Cannot read reply");
+            replies.push(reply.trim_end().to_string());
+        }
+        replies
+    }
+
+    const REQUESTS: [&str; 9] = [
+        "deposit,1,1,10",
+        r#"{"type":"withdrawal","client":1,"tx":2,"amount":"2.5"}"#,
+        "",
+        "withdrawal,1,3,100",
+        "dispute,1,1",
+        "query 1",
+        "query 2",
+        "query x",
+        "bogus,1,4,1",
+    ];
+
+    fn expected() -> Vec<String> {
+        vec![
+            "ok",
+            "ok",
+            "error insufficient_funds Transaction 3: Insufficient funds",
+            "ok",
+            "ok 1,-2.5000,10.0000,7.5000,false",
+            "error unknown_client Unknown client: 2",
+            "error invalid_input Error in input data: `Invalid client x: invalid digit found in string`.",
+            "error invalid_input Error in input data: `Invalid transaction type in transaction 4`.",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+
+    #[test]
+    fn test_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Cannot bind");
+        let addr = listener.local_addr().expect("No local address");
+        let p = Arc::new(Mutex::new(Processor::default()));
+        thread::spawn(move || serve_tcp(listener, p));
+
+        let stream = std::net::TcpStream::connect(addr).expect("Cannot connect");
+        assert_eq!(script(stream, &REQUESTS), expected());
+
+        // The state is shared between connections
+        let stream = std::net::TcpStream::connect(addr).expect("Cannot connect");
+        assert_eq!(
+            script(stream, &["query 1"]),
+            vec!["ok 1,-2.5000,10.0000,7.5000,false"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix() {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let dir = tempfile::tempdir().expect("Cannot create temporary directory");
+        let path = dir.path().join("txn.sock");
+        let listener = UnixListener::bind(&path).expect("Cannot bind");
+        let p = Arc::new(Mutex::new(Processor::default()));
+        thread::spawn(move || serve_unix(listener, p));
+
+        let stream = UnixStream::connect(&path).expect("Cannot connect");
+        assert_eq!(script(stream, &REQUESTS), expected());
+    }
+}
@@ -0,0 +1,73 @@
+//! Counts of the transactions processed, e.g. to report at the end of a run.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::types::Error;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// All transactions processed, whether they were applied or not
+    pub processed: u64,
+    /// The number of rejected transactions for each error code
+    pub rejected: BTreeMap<&'static str, u64>,
+}
+
+impl Summary {
+    pub fn count(&mut self, outcome: &Result<(), Error>) {
+        self.processed += 1;
+        if let Err(e) = outcome {
+            *self.rejected.entry(e.code()).or_default() += 1;
+        }
+    }
+
+    /// The number of transactions rejected with the error code `code`.
+    pub fn rejected(&self, code: &str) -> u64 {
+        self.rejected.get(code).copied().unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: Summary) {
+        self.processed += other.processed;
+        for (code, n) in other.rejected {
+            *self.rejected.entry(code).or_default() += n;
+        }
+    }
+}
+
+/// Displays one `<name>: <count>` line for the processed, applied and rejected transactions,
+/// followed by one for each error code.
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rejected: u64 = self.rejected.values().sum();
+        writeln!(f, "processed: {}", self.processed)?;
+        writeln!(f, "applied: {}", self.processed - rejected)?;
+        write!(f, "rejected: {}", rejected)?;
+        for (code, n) in &self.rejected {
+            write!(f, "\n  {}: {}", code, n)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut s = Summary::default();
+        s.count(&Ok(()));
+        s.count(&Err(Error::DisputeExpired(1)));
+
+        let mut other = Summary::default();
+        other.count(&Err(Error::InsufficientFunds(2)));
+        other.count(&Err(Error::DisputeExpired(3)));
+        s.merge(other);
+
+        assert_eq!(s.rejected("dispute_expired"), 2);
+        assert_eq!(s.rejected("locked_account"), 0);
+        assert_eq!(
+            s.to_string(),
+            "processed: 4\napplied: 1\nrejected: 3\n  dispute_expired: 2\n  insufficient_funds: 1"
+        );
+    }
+}
@@ -1,7 +1,11 @@
+use std::fmt::Display;
+
 use crate::amount::Amount;
 
 pub type ClientId = u16;
 pub type TxnId = u32;
+/// Seconds since the Unix epoch
+pub type Timestamp = u64;
 
 #[derive(Clone, Debug, PartialEq, Hash)]
 pub enum Txn {
@@ -9,26 +13,149 @@ pub enum Txn {
         client: ClientId,
         tx: TxnId,
         amount: Amount,
+        timestamp: Option<Timestamp>,
     },
     Withdrawal {
         client: ClientId,
         tx: TxnId,
         amount: Amount,
+        timestamp: Option<Timestamp>,
     },
     Dispute {
         client: ClientId,
         tx: TxnId,
+        timestamp: Option<Timestamp>,
     },
     Resolve {
         client: ClientId,
         tx: TxnId,
+        timestamp: Option<Timestamp>,
     },
     Chargeback {
         client: ClientId,
         tx: TxnId,
+        timestamp: Option<Timestamp>,
+    },
+    /// A control row that credits interest to all accounts, see `Processor::accrue_interest`.
+    AccrueInterest {
+        tx: TxnId,
+        timestamp: Option<Timestamp>,
+    },
+    /// Interest credited to `client` by `AccrueInterest`. These are only created by the
+    /// processor, they are not accepted as input.
+    Interest {
+        client: ClientId,
+        tx: TxnId,
+        amount: Amount,
+        timestamp: Option<Timestamp>,
+    },
+    /// Moves `amount` from `client` to `to`. Each side can dispute it as it would dispute a
+    /// withdrawal (`client`) or a deposit (`to`), which only affects its own account.
+    Transfer {
+        client: ClientId,
+        to: ClientId,
+        tx: TxnId,
+        amount: Amount,
+        timestamp: Option<Timestamp>,
     },
 }
 
+impl Txn {
+    /// The client the transaction is about. Control rows apply to all clients, so they return 0.
+    pub fn client(&self) -> ClientId {
+        match self {
+            Txn::AccrueInterest { .. } => 0,
+            Txn::Deposit { client, .. }
+            | Txn::Withdrawal { client, .. }
+            | Txn::Dispute { client, .. }
+            | Txn::Resolve { client, .. }
+            | Txn::Chargeback { client, .. }
+            | Txn::Interest { client, .. }
+            | Txn::Transfer { client, .. } => *client,
+        }
+    }
+
+    /// The other client involved in the transaction, if any.
+    pub fn counterparty(&self) -> Option<ClientId> {
+        match self {
+            Txn::Transfer { to, .. } => Some(*to),
+            _ => None,
+        }
+    }
+
+    /// When the transaction happened, if the input says so.
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        match self {
+            Txn::Deposit { timestamp, .. }
+            | Txn::Withdrawal { timestamp, .. }
+            | Txn::Dispute { timestamp, .. }
+            | Txn::Resolve { timestamp, .. }
+            | Txn::Chargeback { timestamp, .. }
+            | Txn::AccrueInterest { timestamp, .. }
+            | Txn::Interest { timestamp, .. }
+            | Txn::Transfer { timestamp, .. } => *timestamp,
+        }
+    }
+
+    /// The amount the transaction moves, if it has one.
+    pub fn amount(&self) -> Option<Amount> {
+        match self {
+            Txn::Deposit { amount, .. }
+            | Txn::Withdrawal { amount, .. }
+            | Txn::Interest { amount, .. }
+            | Txn::Transfer { amount, .. } => Some(*amount),
+            _ => None,
+        }
+    }
+
+    pub fn tx(&self) -> TxnId {
+        match self {
+            Txn::Deposit { tx, .. }
+            | Txn::Withdrawal { tx, .. }
+            | Txn::Dispute { tx, .. }
+            | Txn::Resolve { tx, .. }
+            | Txn::Chargeback { tx, .. }
+            | Txn::AccrueInterest { tx, .. }
+            | Txn::Interest { tx, .. }
+            | Txn::Transfer { tx, .. } => *tx,
+        }
+    }
+}
+
+/// Displays a transaction as a CSV row, in the same format as the input. The timestamp is only
+/// written if there is one, as a sixth column after `to`.
+impl Display for Txn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Txn::Deposit {
+                client, tx, amount, ..
+            } => write!(f, "deposit,{},{},{}", client, tx, amount)?,
+            Txn::Withdrawal {
+                client, tx, amount, ..
+            } => write!(f, "withdrawal,{},{},{}", client, tx, amount)?,
+            Txn::Dispute { client, tx, .. } => write!(f, "dispute,{},{},", client, tx)?,
+            Txn::Resolve { client, tx, .. } => write!(f, "resolve,{},{},", client, tx)?,
+            Txn::Chargeback { client, tx, .. } => write!(f, "chargeback,{},{},", client, tx)?,
+            Txn::Transfer {
+                client,
+                to,
+                tx,
+                amount,
+                ..
+            } => write!(f, "transfer,{},{},{},{}", client, tx, amount, to)?,
+            Txn::AccrueInterest { tx, .. } => write!(f, "accrue_interest,,{},", tx)?,
+            Txn::Interest {
+                client, tx, amount, ..
+            } => write!(f, "interest,{},{},{}", client, tx, amount)?,
+        }
+        match (self.timestamp(), self) {
+            (None, _) => Ok(()),
+            (Some(t), Txn::Transfer { .. }) => write!(f, ",{}", t),
+            (Some(t), _) => write!(f, ",,{}", t),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
 pub struct AccountData {
     pub client: ClientId,
@@ -42,12 +169,14 @@ pub enum Account {
     Unlocked(AccountData),
 }
 
-#[derive(Debug, PartialEq, thiserror::Error)]
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
 pub enum Error {
     #[error("Deserialization error in file {0}: `{1}`")]
     Deserialization(String, String),
     #[error("Serialization error: `{0}`")]
     Serialization(String),
+    #[error("Output error in file {0}: `{1}`")]
+    Output(String, String),
     #[error("Error in input data: `{0}`.")]
     Input(String),
     #[error("Transaction {0}: Insufficient funds")]
@@ -58,4 +187,38 @@ pub enum Error {
     NonexistentAccount(TxnId, ClientId),
     #[error("Transaction {0}: Locked account: {1}")]
     LockedAccount(TxnId, ClientId),
+    #[error("Unknown client: {0}")]
+    UnknownClient(ClientId),
+    #[error("Transaction {0}: Exceeds the maximum amount for a single withdrawal")]
+    WithdrawalLimit(TxnId),
+    #[error("Transaction {0}: Exceeds the maximum total withdrawn in a day")]
+    DailyLimit(TxnId),
+    #[error("Transaction {0}: Too many transactions in a short time")]
+    VelocityLimit(TxnId),
+    #[error("Transaction {0}: Earlier than the previous transaction of client {1}")]
+    TimestampOutOfOrder(TxnId, ClientId),
+    #[error("Transaction {0}: Too late to dispute")]
+    DisputeExpired(TxnId),
+}
+
+impl Error {
+    /// A short, stable identifier for the kind of error, meant to be used by clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Deserialization(..) => "deserialization",
+            Error::Serialization(..) => "serialization",
+            Error::Output(..) => "output",
+            Error::Input(..) => "invalid_input",
+            Error::InsufficientFunds(..) => "insufficient_funds",
+            Error::InvalidTransaction(..) => "invalid_transaction",
+            Error::NonexistentAccount(..) => "nonexistent_account",
+            Error::LockedAccount(..) => "locked_account",
+            Error::UnknownClient(..) => "unknown_client",
+            Error::WithdrawalLimit(..) => "withdrawal_limit_exceeded",
+            Error::DailyLimit(..) => "daily_limit_exceeded",
+            Error::VelocityLimit(..) => "velocity_limit_exceeded",
+            Error::TimestampOutOfOrder(..) => "timestamp_out_of_order",
+            Error::DisputeExpired(..) => "dispute_expired",
+        }
+    }
 }